@@ -0,0 +1,245 @@
+use crate::{Action, Decision, FilterTiming, InputMessage, Kind, OutputMessage, Pubkey};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The `[audit]` section of noteguard.toml
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct AuditConfig {
+    /// The file to write decisions to, one JSON object per line
+    pub path: PathBuf,
+
+    /// Rotate the file once it grows past this many bytes. Files are
+    /// never rotated if this isn't set.
+    pub max_bytes: Option<u64>,
+
+    /// The number of rotated files to keep, eg: `audit.jsonl.1`
+    #[serde(default = "default_max_files")]
+    pub max_files: u32,
+}
+
+fn default_max_files() -> u32 {
+    5
+}
+
+/// A line in the audit log. Input lines that couldn't be parsed only have the
+/// id that could be recovered from them.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditRecord<'a> {
+    /// Unix timestamp of the decision
+    time: u64,
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pubkey: Option<Pubkey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<Kind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_type: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_info: Option<&'a str>,
+    action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<&'a str>,
+    filters: &'a [FilterTiming],
+}
+
+/// Writes every decision made by the pipeline to a JSONL file
+pub struct AuditLog {
+    config: AuditConfig,
+    file: File,
+    size: u64,
+}
+
+impl AuditLog {
+    pub fn open(config: AuditConfig) -> io::Result<Self> {
+        let file = open_append(&config.path)?;
+        let size = file.metadata()?.len();
+        Ok(AuditLog { config, file, size })
+    }
+
+    pub fn config(&self) -> &AuditConfig {
+        &self.config
+    }
+
+    pub fn write(&mut self, input: &InputMessage, decision: &Decision) -> io::Result<()> {
+        self.append(&AuditRecord {
+            time: now(),
+            id: &decision.output.id,
+            pubkey: Some(input.event.pubkey),
+            kind: Some(input.event.kind),
+            source_type: Some(&input.source_type),
+            source_info: Some(&input.source_info),
+            action: decision.output.action,
+            msg: decision.output.msg.as_deref(),
+            filter: decision.filter.as_deref(),
+            filters: &decision.timings,
+        })
+    }
+
+    /// Record the response to an input line that couldn't be parsed, with
+    /// `filter` set to `"input"`
+    pub fn write_malformed(&mut self, output: &OutputMessage) -> io::Result<()> {
+        self.append(&AuditRecord {
+            time: now(),
+            id: &output.id,
+            pubkey: None,
+            kind: None,
+            source_type: None,
+            source_info: None,
+            action: output.action,
+            msg: output.msg.as_deref(),
+            filter: Some("input"),
+            filters: &[],
+        })
+    }
+
+    fn append(&mut self, record: &AuditRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        if let Some(max_bytes) = self.config.max_bytes {
+            if self.size > 0 && self.size + line.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shift `path.N` to `path.N+1`, dropping the oldest file, then move the
+    /// current file to `path.1` and start a new one
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = &self.config.path;
+
+        if self.config.max_files == 0 {
+            std::fs::remove_file(path)?;
+        } else {
+            for n in (1..self.config.max_files).rev() {
+                let from = rotated_path(path, n);
+                if from.exists() {
+                    std::fs::rename(from, rotated_path(path, n + 1))?;
+                }
+            }
+            std::fs::rename(path, rotated_path(path, 1))?;
+        }
+
+        self.file = open_append(path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    PathBuf::from(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Note, OutputMessage, Signature, Tags};
+
+    const ID: &str = "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76";
+
+    #[test]
+    fn test_audit_log_rotation() {
+        let dir = std::env::temp_dir().join(format!("noteguard-audit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create dir");
+        let path = dir.join("audit.jsonl");
+
+        let input = InputMessage {
+            message_type: "new".to_string(),
+            event: Note {
+                id: ID.parse().unwrap(),
+                pubkey: "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93"
+                    .parse()
+                    .unwrap(),
+                created_at: 0,
+                kind: Kind(1),
+                tags: Tags::default(),
+                content: "mock_content".to_string(),
+                sig: Signature::from_bytes([0; 64]),
+            },
+            received_at: 0,
+            source_type: "IP4".to_string(),
+            source_info: "127.0.0.1".to_string(),
+        };
+
+        let decision = Decision {
+            output: OutputMessage::new(
+                ID.to_string(),
+                Action::Reject,
+                Some("blocked: nope".to_string()),
+            ),
+            filter: Some("kinds".to_string()),
+            timings: vec![FilterTiming {
+                name: "kinds".to_string(),
+                action: Action::Reject,
+                monitor: false,
+                micros: 3,
+            }],
+        };
+
+        // measure a line, then rotate whenever a third line would be written
+        let mut audit = AuditLog::open(AuditConfig {
+            path: path.clone(),
+            max_bytes: None,
+            max_files: 1,
+        })
+        .expect("Failed to open audit log");
+        audit
+            .write(&input, &decision)
+            .expect("Failed to write audit log");
+        let line_len = audit.size;
+        std::fs::remove_file(&path).expect("Failed to remove audit log");
+
+        let mut audit = AuditLog::open(AuditConfig {
+            path: path.clone(),
+            max_bytes: Some(line_len * 5 / 2),
+            max_files: 1,
+        })
+        .expect("Failed to open audit log");
+
+        for _ in 0..3 {
+            audit
+                .write(&input, &decision)
+                .expect("Failed to write audit log");
+        }
+
+        let current = std::fs::read_to_string(&path).expect("Failed to read audit log");
+        let rotated =
+            std::fs::read_to_string(rotated_path(&path, 1)).expect("Failed to read audit log");
+        assert_eq!(current.lines().count(), 1);
+        assert_eq!(rotated.lines().count(), 2);
+        assert!(!rotated_path(&path, 2).exists());
+
+        let record: serde_json::Value =
+            serde_json::from_str(current.lines().next().unwrap()).expect("invalid json");
+        assert_eq!(record["id"], ID);
+        assert_eq!(record["sourceInfo"], "127.0.0.1");
+        assert_eq!(record["action"], "reject");
+        assert_eq!(record["filter"], "kinds");
+        assert_eq!(record["filters"][0]["micros"], 3);
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove dir");
+    }
+}
@@ -0,0 +1,539 @@
+use crate::filters::normalize_host;
+use crate::list::read_lines;
+use crate::{AuditConfig, InputMessage, IpSet, Kind, Pubkey};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The contents of a noteguard.toml file
+#[derive(Deserialize, Default, Clone)]
+pub struct Config {
+    /// The order in which the filters are run
+    pub pipeline: Vec<String>,
+
+    /// Filter settings, keyed by filter name. The filter type is given by
+    /// the `type` key, and defaults to the name.
+    #[serde(default)]
+    pub filters: HashMap<String, toml::Value>,
+
+    /// Lists shared between filters. Any filter setting can use a list by
+    /// setting it to `"@<name>"`.
+    #[serde(default)]
+    pub lists: HashMap<String, ListConfig>,
+
+    /// Alternate pipelines for matching notes. The first matching route is
+    /// used, and notes that don't match any route use `pipeline`.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+
+    /// Write every decision to a JSONL file
+    pub audit: Option<AuditConfig>,
+
+    /// Serve Prometheus metrics, requires the `metrics` feature
+    pub metrics: Option<MetricsConfig>,
+}
+
+/// The `[metrics]` section of noteguard.toml
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct MetricsConfig {
+    /// A tcp address like `127.0.0.1:9184`, or a unix socket path prefixed
+    /// with `unix:`
+    pub listen: String,
+}
+
+/// What a `[lists.<name>]` section contains, used to check its entries
+#[derive(Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListType {
+    /// Hex pubkeys
+    Pubkeys,
+
+    /// Ip addresses or CIDR ranges
+    Ips,
+
+    /// Domain names
+    Domains,
+
+    /// Words or phrases
+    Words,
+}
+
+impl fmt::Display for ListType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ListType::Pubkeys => "pubkeys",
+            ListType::Ips => "ips",
+            ListType::Domains => "domains",
+            ListType::Words => "words",
+        })
+    }
+}
+
+/// The type of list a builtin filter's setting holds, for the settings that
+/// can use a `[lists.<name>]` section
+fn setting_list_type(filter_type: &str, setting: &str) -> Option<ListType> {
+    match (filter_type, setting) {
+        ("blacklist" | "whitelist", "pubkeys") | ("urls", "known_pubkeys") => {
+            Some(ListType::Pubkeys)
+        }
+        ("blacklist" | "whitelist", "ips") | ("ratelimit", "whitelist") => Some(ListType::Ips),
+        ("urls", "block_domains" | "block_suffixes" | "allow_domains" | "allow_suffixes") => {
+            Some(ListType::Domains)
+        }
+        ("content", "filters") => Some(ListType::Words),
+        _ => None,
+    }
+}
+
+/// The list name in a `"@<name>"` setting. Other strings starting with `@`,
+/// like `"@everyone hi"`, aren't list references.
+fn list_reference(value: &str) -> Option<&str> {
+    value.strip_prefix('@').filter(|name| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+/// A `[lists.<name>]` section, with either inline `entries` or a `file`
+/// with one entry per line
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ListConfig {
+    #[serde(rename = "type")]
+    pub list_type: ListType,
+
+    pub entries: Option<Vec<String>>,
+
+    pub file: Option<PathBuf>,
+}
+
+impl ListConfig {
+    /// The value that replaces `"@<name>"` in filter settings
+    fn value(&self) -> toml::Value {
+        match (&self.entries, &self.file) {
+            (_, Some(file)) => {
+                let mut table = toml::value::Table::new();
+                table.insert("file".into(), file.display().to_string().into());
+                toml::Value::Table(table)
+            }
+            (entries, None) => toml::Value::Array(
+                entries
+                    .iter()
+                    .flatten()
+                    .map(|entry| entry.as_str().into())
+                    .collect(),
+            ),
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let entries: Vec<(String, String)> = match (&self.entries, &self.file) {
+            (Some(entries), None) => entries
+                .iter()
+                .enumerate()
+                .map(|(ind, entry)| (format!("entries[{}]", ind), entry.clone()))
+                .collect(),
+            (None, Some(file)) => read_lines(file)?
+                .into_iter()
+                .map(|(line_number, line)| (format!("{}:{}", file.display(), line_number), line))
+                .collect(),
+            _ => return Err("expected exactly one of 'entries' or 'file'".to_string()),
+        };
+
+        for (location, entry) in entries {
+            let valid = match self.list_type {
+                ListType::Pubkeys => entry.parse::<Pubkey>().is_ok(),
+                ListType::Ips => IpSet::default().insert(&entry).is_ok(),
+                ListType::Domains => normalize_host(&entry).is_some(),
+                ListType::Words => !entry.is_empty(),
+            };
+
+            if !valid {
+                let expected = match self.list_type {
+                    ListType::Pubkeys => "a hex pubkey",
+                    ListType::Ips => "an ip address or CIDR range",
+                    ListType::Domains => "a domain",
+                    ListType::Words => "a word",
+                };
+                return Err(format!("{}: '{}' is not {}", location, entry, expected));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How the pipeline treats a filter's decisions, set with the `mode` key of
+/// a `[filters.<name>]` section
+#[derive(Deserialize, Default, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    /// Decisions are applied
+    #[default]
+    Enforce,
+
+    /// Decisions other than accept are logged, and the note is accepted
+    /// as if the filter wasn't there
+    Monitor,
+}
+
+/// A `[[routes]]` entry. A note matches when it matches every criteria that
+/// is set, a route without any criteria matches every note.
+#[derive(Deserialize, Default, Clone)]
+pub struct Route {
+    /// Note kinds
+    pub kinds: Option<Vec<Kind>>,
+
+    /// strfry source types, eg: `IP4`, `IP6`, `Import`, `Stream`, `Sync`
+    #[serde(rename = "sourceType", alias = "source_type")]
+    pub source_types: Option<Vec<String>>,
+
+    /// Ip addresses or CIDR ranges of the client that sent the note. Notes
+    /// from other sources, like imports, never match.
+    #[serde(alias = "sourceInfo")]
+    pub sources: Option<IpSet>,
+
+    /// The order in which the filters are run for matching notes
+    pub pipeline: Vec<String>,
+}
+
+impl Route {
+    pub fn matches(&self, input: &InputMessage) -> bool {
+        self.kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.contains(&input.event.kind))
+            && self
+                .source_types
+                .as_ref()
+                .is_none_or(|source_types| source_types.contains(&input.source_type))
+            && self
+                .sources
+                .as_ref()
+                .is_none_or(|sources| input.source_ip().is_some_and(|ip| sources.contains(ip)))
+    }
+}
+
+impl Config {
+    /// The names used by the pipeline and by every route, without duplicates
+    pub fn pipeline_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec![];
+        let routes = self.routes.iter().flat_map(|route| route.pipeline.iter());
+
+        for name in self.pipeline.iter().chain(routes) {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+
+        names
+    }
+
+    /// Check the entries of every `[lists.<name>]` section
+    pub fn check_lists(&self) -> Vec<ConfigError> {
+        let mut names: Vec<&String> = self.lists.keys().collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                self.lists[name]
+                    .check()
+                    .err()
+                    .map(|message| ConfigError::InvalidList {
+                        name: name.clone(),
+                        message,
+                    })
+            })
+            .collect()
+    }
+
+    /// Replace `"@<name>"` list settings in the config of filter `filter`,
+    /// of type `filter_type`, with the named list. Other settings are left
+    /// alone, so `message = "@everyone"` is just a message.
+    pub(crate) fn substitute_lists(
+        &self,
+        filter: &str,
+        filter_type: &str,
+        value: &mut toml::Value,
+    ) -> Result<(), ConfigError> {
+        let Some(table) = value.as_table_mut() else {
+            return Ok(());
+        };
+
+        for (setting, value) in table.iter_mut() {
+            let Some(expected) = setting_list_type(filter_type, setting) else {
+                continue;
+            };
+
+            let Some(name) = value.as_str().and_then(list_reference) else {
+                continue;
+            };
+
+            let list = self
+                .lists
+                .get(name)
+                .ok_or_else(|| ConfigError::UnknownList {
+                    filter: filter.to_owned(),
+                    list: name.to_owned(),
+                })?;
+
+            if expected != list.list_type {
+                return Err(ConfigError::WrongListType {
+                    filter: filter.to_owned(),
+                    setting: setting.clone(),
+                    list: name.to_owned(),
+                    expected,
+                    found: list.list_type,
+                });
+            }
+
+            *value = list.value();
+        }
+
+        Ok(())
+    }
+
+    /// Read and parse a config file
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        contents.parse()
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut unknown: Vec<String> = vec![];
+        let mut deserializer = toml::Deserializer::new(s);
+        let config = serde_ignored::deserialize(&mut deserializer, |path| {
+            unknown.push(setting_path(&path))
+        })?;
+
+        match unknown.into_iter().next() {
+            Some(path) => Err(ConfigError::UnknownSetting { path }),
+            None => Ok(config),
+        }
+    }
+}
+
+/// Format an ignored setting's path like serde_path_to_error does, without
+/// serde_ignored's `?` markers for options and newtypes
+fn setting_path(path: &serde_ignored::Path) -> String {
+    let mut keys = vec![];
+    let mut path = path;
+
+    loop {
+        path = match path {
+            serde_ignored::Path::Root => break,
+            serde_ignored::Path::Seq { parent, index } => {
+                keys.push(index.to_string());
+                parent
+            }
+            serde_ignored::Path::Map { parent, key } => {
+                keys.push(key.clone());
+                parent
+            }
+            serde_ignored::Path::Some { parent }
+            | serde_ignored::Path::NewtypeStruct { parent }
+            | serde_ignored::Path::NewtypeVariant { parent } => parent,
+        };
+    }
+
+    keys.reverse();
+    keys.join(".")
+}
+
+/// A filter setting that could not be deserialized
+pub(crate) struct SettingsError {
+    /// The path of the setting within the filter's section, `.` for the
+    /// section itself
+    pub path: String,
+    pub source: toml::de::Error,
+}
+
+/// Deserialize a filter's settings. Settings that aren't used by the filter
+/// are errors too, so a typo doesn't silently fall back to a default.
+pub(crate) fn deserialize_settings<T: DeserializeOwned>(
+    value: toml::Value,
+) -> Result<T, SettingsError> {
+    let mut unknown: Vec<String> = vec![];
+    let mut track = serde_path_to_error::Track::new();
+
+    let result = serde_ignored::deserialize(
+        serde_path_to_error::Deserializer::new(value, &mut track),
+        |path| unknown.push(setting_path(&path)),
+    );
+
+    let settings = result.map_err(|source| SettingsError {
+        path: track.path().to_string(),
+        source,
+    })?;
+
+    match unknown.into_iter().next() {
+        Some(path) => Err(SettingsError {
+            path,
+            source: serde::de::Error::custom("unknown setting"),
+        }),
+        None => Ok(settings),
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// The config is not valid TOML, or is missing the pipeline
+    Parse(toml::de::Error),
+
+    /// A pipeline or route entry has no `[filters.<name>]` section
+    MissingFilter { name: String },
+
+    /// A pipeline entry's filter type doesn't match any registered filter
+    UnknownFilter { name: String, filter_type: String },
+
+    /// A filter's settings could not be deserialized. `path` is the TOML
+    /// path of the offending value, eg: `filters.ratelimit.posts_per_minute`
+    InvalidFilter {
+        name: String,
+        path: String,
+        source: toml::de::Error,
+    },
+
+    /// A top-level setting that noteguard doesn't use, eg: `[filter.x]`
+    /// instead of `[filters.x]`
+    UnknownSetting { path: String },
+
+    /// A `[filters.<name>]` section that isn't used by the pipeline or by
+    /// any route
+    UnusedFilter { name: String },
+
+    /// The audit log could not be opened
+    Audit {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// The metrics listener could not be started
+    Metrics {
+        listen: String,
+        source: std::io::Error,
+    },
+
+    /// A combinator that includes itself, directly or through other
+    /// combinators
+    FilterCycle { name: String },
+
+    /// A `[lists.<name>]` section with a missing file or invalid entry
+    InvalidList { name: String, message: String },
+
+    /// A filter setting refers to a `"@<name>"` list that isn't defined
+    UnknownList { filter: String, list: String },
+
+    /// A filter setting refers to a list of the wrong type, eg: an `ips`
+    /// list used for pubkeys
+    WrongListType {
+        filter: String,
+        setting: String,
+        list: String,
+        expected: ListType,
+        found: ListType,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "could not read config {}: {}", path.display(), source)
+            }
+            ConfigError::Parse(err) => write!(f, "could not parse config: {}", err),
+            ConfigError::MissingFilter { name } => write!(
+                f,
+                "pipeline entry '{}' has no [filters.{}] section",
+                name, name
+            ),
+            ConfigError::UnknownFilter { name, filter_type } => write!(
+                f,
+                "pipeline entry '{}' has unknown filter type '{}'",
+                name, filter_type
+            ),
+            ConfigError::InvalidFilter { name, path, source } => {
+                write!(
+                    f,
+                    "invalid settings for filter '{}' at {}: {}",
+                    name, path, source
+                )
+            }
+            ConfigError::Audit { path, source } => {
+                write!(f, "could not open audit log {}: {}", path.display(), source)
+            }
+            ConfigError::Metrics { listen, source } => {
+                write!(f, "could not serve metrics on {}: {}", listen, source)
+            }
+            ConfigError::FilterCycle { name } => write!(f, "filter '{}' includes itself", name),
+            ConfigError::InvalidList { name, message } => {
+                write!(f, "invalid list [lists.{}]: {}", name, message)
+            }
+            ConfigError::UnknownList { filter, list } => write!(
+                f,
+                "filter '{}' uses '@{}', but there is no [lists.{}] section",
+                filter, list, list
+            ),
+            ConfigError::WrongListType {
+                filter,
+                setting,
+                list,
+                expected,
+                found,
+            } => write!(
+                f,
+                "filter '{}' setting '{}' needs a list of type '{}', but [lists.{}] has type '{}'",
+                filter, setting, expected, list, found
+            ),
+            ConfigError::UnknownSetting { path } => write!(f, "unknown setting '{}'", path),
+            ConfigError::UnusedFilter { name } => {
+                write!(f, "[filters.{}] is not used by the pipeline", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Audit { source, .. } => Some(source),
+            ConfigError::Metrics { source, .. } => Some(source),
+            ConfigError::Parse(err) => Some(err),
+            ConfigError::InvalidFilter { source, .. } => Some(source),
+            ConfigError::MissingFilter { .. }
+            | ConfigError::UnknownFilter { .. }
+            | ConfigError::UnknownSetting { .. }
+            | ConfigError::UnusedFilter { .. }
+            | ConfigError::FilterCycle { .. }
+            | ConfigError::InvalidList { .. }
+            | ConfigError::UnknownList { .. }
+            | ConfigError::WrongListType { .. } => None,
+        }
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Parse(err)
+    }
+}
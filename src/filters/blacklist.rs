@@ -1,39 +1,88 @@
-use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use crate::{Action, InputMessage, IpSet, List, NoteFilter, OutputMessage, Pubkey};
 use serde::Deserialize;
+use std::collections::HashSet;
 
 #[derive(Deserialize, Default)]
 pub struct Blacklist {
-    pub pubkeys: Option<Vec<String>>,
-    pub ips: Option<Vec<String>>,
+    pub pubkeys: Option<List<HashSet<Pubkey>>>,
+    pub ips: Option<List<IpSet>>,
+}
+
+impl Blacklist {
+    fn reload(&mut self) {
+        if let Some(pubkeys) = &mut self.pubkeys {
+            pubkeys.reload();
+        }
+        if let Some(ips) = &mut self.ips {
+            ips.reload();
+        }
+    }
 }
 
 impl NoteFilter for Blacklist {
     fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage {
+        self.reload();
+
         let reject_message = "blocked: pubkey/ip is blacklisted".to_string();
         if let Some(pubkeys) = &self.pubkeys {
             if pubkeys.contains(&msg.event.pubkey) {
                 return OutputMessage::new(
-                    msg.event.id.clone(),
+                    msg.event.id.to_string(),
                     Action::Reject,
                     Some(reject_message),
                 );
             }
         }
 
-        if let Some(ips) = &self.ips {
-            if ips.contains(&msg.source_info) {
+        if let (Some(ips), Some(addr)) = (&self.ips, msg.source_ip()) {
+            if ips.contains(addr) {
                 return OutputMessage::new(
-                    msg.event.id.clone(),
+                    msg.event.id.to_string(),
                     Action::Reject,
                     Some(reject_message),
                 );
             }
         }
 
-        OutputMessage::new(msg.event.id.clone(), Action::Accept, None)
+        OutputMessage::new(msg.event.id.to_string(), Action::Accept, None)
     }
 
     fn name(&self) -> &'static str {
         "blacklist"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input, OTHER_PUBKEY};
+
+    #[test]
+    fn test_blacklist() {
+        let mut filter: Blacklist = load_filter(&format!(
+            r#"
+            pubkeys = ["{}"]
+            ips = ["203.0.113.0/24", "2001:db8::/32"]
+        "#,
+            OTHER_PUBKEY
+        ));
+
+        let run = |filter: &mut Blacklist, source_type: &str, source_info: &str| {
+            let mut input_message = mock_input();
+            input_message.source_type = source_type.to_string();
+            input_message.source_info = source_info.to_string();
+            filter.filter_note(&input_message).action
+        };
+
+        assert_eq!(run(&mut filter, "IP4", "203.0.113.9"), Action::Reject);
+        assert_eq!(run(&mut filter, "IP6", "2001:db8::1"), Action::Reject);
+        assert_eq!(run(&mut filter, "IP4", "192.0.2.1"), Action::Accept);
+
+        // ip lists never match other kinds of sources
+        assert_eq!(run(&mut filter, "Stream", "203.0.113.9"), Action::Accept);
+
+        let mut input_message = mock_input();
+        input_message.event.pubkey = OTHER_PUBKEY.parse().expect("invalid pubkey");
+        assert_eq!(filter.filter_note(&input_message).action, Action::Reject);
+    }
+}
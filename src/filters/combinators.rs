@@ -0,0 +1,189 @@
+use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use serde::Deserialize;
+
+/// How a [`Combinator`] combines the results of its filters
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CombinatorKind {
+    /// Matches when every filter rejects the note
+    All,
+
+    /// Matches when any filter rejects the note
+    Any,
+
+    /// Matches when its single filter accepts the note
+    Not,
+
+    /// Runs its filters like a nested pipeline, the first filter that
+    /// rejects (or finally accepts) the note decides
+    First,
+}
+
+impl CombinatorKind {
+    /// Filter types that are reserved for combinators
+    pub fn from_type(filter_type: &str) -> Option<Self> {
+        match filter_type {
+            "all" => Some(CombinatorKind::All),
+            "any" => Some(CombinatorKind::Any),
+            "not" => Some(CombinatorKind::Not),
+            "first" => Some(CombinatorKind::First),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CombinatorKind::All => "all",
+            CombinatorKind::Any => "any",
+            CombinatorKind::Not => "not",
+            CombinatorKind::First => "first",
+        }
+    }
+}
+
+/// The settings of a combinator in noteguard.toml. `filters` are the names
+/// of other `[filters.<name>]` sections.
+#[derive(Deserialize)]
+pub struct CombinatorConfig {
+    pub filters: Vec<String>,
+    pub action: Option<Action>,
+    pub message: Option<String>,
+}
+
+/// A filter built out of other filters. When it matches, it returns its
+/// configured action and message. If these are not set, the result of the
+/// filter that decided the match is returned instead.
+pub struct Combinator {
+    kind: CombinatorKind,
+    filters: Vec<Box<dyn NoteFilter>>,
+    action: Option<Action>,
+    message: Option<String>,
+}
+
+fn rejected(out: &OutputMessage) -> bool {
+    matches!(out.action, Action::Reject | Action::ShadowReject)
+}
+
+impl Combinator {
+    pub fn new(kind: CombinatorKind, filters: Vec<Box<dyn NoteFilter>>) -> Self {
+        Combinator {
+            kind,
+            filters,
+            action: None,
+            message: None,
+        }
+    }
+
+    pub fn all(filters: Vec<Box<dyn NoteFilter>>) -> Self {
+        Combinator::new(CombinatorKind::All, filters)
+    }
+
+    pub fn any(filters: Vec<Box<dyn NoteFilter>>) -> Self {
+        Combinator::new(CombinatorKind::Any, filters)
+    }
+
+    pub fn not(filter: Box<dyn NoteFilter>) -> Self {
+        Combinator::new(CombinatorKind::Not, vec![filter])
+    }
+
+    pub fn first(filters: Vec<Box<dyn NoteFilter>>) -> Self {
+        Combinator::new(CombinatorKind::First, filters)
+    }
+
+    pub fn with_action(mut self, action: Action) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// The result of the filter that decided the match, if any
+    fn matched(&mut self, msg: &InputMessage) -> Option<OutputMessage> {
+        match self.kind {
+            CombinatorKind::All => {
+                let mut last = None;
+                for filter in &mut self.filters {
+                    let out = filter.filter_note(msg);
+                    if !rejected(&out) {
+                        return None;
+                    }
+                    last = Some(out);
+                }
+                last
+            }
+
+            CombinatorKind::Any => self
+                .filters
+                .iter_mut()
+                .map(|filter| filter.filter_note(msg))
+                .find(rejected),
+
+            CombinatorKind::Not => {
+                let out = self.filters.first_mut()?.filter_note(msg);
+                if rejected(&out) {
+                    None
+                } else {
+                    Some(OutputMessage::new(
+                        msg.event.id.to_string(),
+                        Action::Reject,
+                        Some("blocked: note is not allowed here".to_string()),
+                    ))
+                }
+            }
+
+            CombinatorKind::First => self
+                .filters
+                .iter_mut()
+                .map(|filter| filter.filter_note(msg))
+                .find(|out| out.action != Action::Accept),
+        }
+    }
+}
+
+impl NoteFilter for Combinator {
+    fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage {
+        let Some(mut out) = self.matched(msg) else {
+            return OutputMessage::new(msg.event.id.to_string(), Action::Accept, None);
+        };
+
+        if let Some(action) = self.action {
+            out.action = action;
+        }
+
+        if self.message.is_some() {
+            out.msg = self.message.clone();
+        }
+
+        out
+    }
+
+    fn observe(&mut self, msg: &InputMessage) {
+        for filter in &mut self.filters {
+            filter.observe(msg);
+        }
+    }
+
+    /// The gauges of every filter, summed when several report the same one
+    fn gauges(&self) -> Vec<(&'static str, u64)> {
+        let mut gauges: Vec<(&'static str, u64)> = vec![];
+        for (gauge, value) in self.filters.iter().flat_map(|filter| filter.gauges()) {
+            match gauges.iter_mut().find(|(name, _)| *name == gauge) {
+                Some((_, total)) => *total += value,
+                None => gauges.push((gauge, value)),
+            }
+        }
+        gauges
+    }
+
+    fn accepted(&mut self, msg: &InputMessage) {
+        for filter in &mut self.filters {
+            filter.accepted(msg);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.kind.name()
+    }
+}
@@ -1,23 +1,451 @@
-use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use super::normalize::{is_bidi_control, Normalize};
+use crate::{Action, InputMessage, List, NoteFilter, OutputMessage};
+use aho_corasick::AhoCorasick;
+use log::{error, info};
+use regex::RegexSet;
 use serde::Deserialize;
 
+/// How a content rule's pattern is matched
+#[derive(Deserialize, Default, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchType {
+    /// The pattern appears anywhere in the content
+    #[default]
+    Substring,
+
+    /// The pattern is a regular expression
+    Regex,
+
+    /// The pattern appears as a whole word or phrase
+    Word,
+}
+
+/// What happens to notes that match a content rule
+#[derive(Deserialize, Default, Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleAction {
+    /// Accept the note, but log that it matched
+    Flag,
+
+    #[default]
+    ShadowReject,
+
+    Reject,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ContentRule {
+    pub pattern: String,
+
+    #[serde(default, rename = "match")]
+    pub match_type: MatchType,
+
+    #[serde(default)]
+    pub ignore_case: bool,
+
+    #[serde(default)]
+    pub action: RuleAction,
+
+    /// The message returned to the client, or logged when flagging
+    pub message: Option<String>,
+}
+
 #[derive(Deserialize, Default)]
+pub struct ContentConfig {
+    /// Substrings that shadow reject notes, kept for older configs
+    #[serde(default)]
+    pub filters: List<Vec<String>>,
+
+    #[serde(default)]
+    pub rules: Vec<ContentRule>,
+
+    /// Fold content and patterns before matching. Regex patterns are
+    /// used as written, so they should match the normalized text.
+    pub normalize: Option<Normalize>,
+
+    /// Reject content containing bidi embedding, override or isolate
+    /// controls, which can hide text or make it read differently
+    #[serde(default)]
+    pub reject_bidi_overrides: bool,
+}
+
+/// A multi-pattern matcher over one group of rules, mapping pattern
+/// indices back to rule indices
+struct Matcher<M> {
+    matcher: M,
+    rules: Vec<usize>,
+}
+
+/// The matchers for rules sharing a case folding setting
+/// A regex matching `word` as a whole word or phrase. `\b` only works next to
+/// word characters, so edges like the `$` in `$SOL` need a non-word character
+/// or the end of the content instead.
+fn word_pattern(word: &str) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+    let start = if is_word(word.chars().next()) {
+        r"\b"
+    } else {
+        r"(?:^|\W)"
+    };
+    let end = if is_word(word.chars().next_back()) {
+        r"\b"
+    } else {
+        r"(?:\W|$)"
+    };
+
+    format!("{}{}{}", start, regex::escape(word), end)
+}
+
+struct Matchers {
+    substrings: Matcher<AhoCorasick>,
+    regexes: Matcher<RegexSet>,
+}
+
+impl Matchers {
+    fn new(
+        rules: &[ContentRule],
+        ignore_case: bool,
+        normalize: &Normalize,
+    ) -> Result<Self, String> {
+        let mut substrings = (vec![], vec![]);
+        let mut regexes = (vec![], vec![]);
+
+        let flags = if ignore_case { "i" } else { "" };
+        let fold = |pattern: &str| {
+            if ignore_case {
+                normalize.apply_lowercase(pattern)
+            } else {
+                normalize.apply(pattern).into_owned()
+            }
+        };
+
+        for (ind, rule) in rules.iter().enumerate() {
+            if rule.ignore_case != ignore_case {
+                continue;
+            }
+
+            let (patterns, indices) = match rule.match_type {
+                MatchType::Substring => (&mut substrings.0, &mut substrings.1),
+                MatchType::Regex | MatchType::Word => (&mut regexes.0, &mut regexes.1),
+            };
+
+            patterns.push(match rule.match_type {
+                MatchType::Substring => fold(&rule.pattern),
+                MatchType::Regex => format!("(?{}:{})", flags, rule.pattern),
+                MatchType::Word => format!("(?{}:{})", flags, word_pattern(&fold(&rule.pattern))),
+            });
+            indices.push(ind);
+        }
+
+        Ok(Matchers {
+            substrings: AhoCorasick::new(&substrings.0)
+                .map(|matcher| Matcher {
+                    matcher,
+                    rules: substrings.1,
+                })
+                .map_err(|err| err.to_string())?,
+            regexes: RegexSet::new(&regexes.0)
+                .map(|matcher| Matcher {
+                    matcher,
+                    rules: regexes.1,
+                })
+                .map_err(|err| err.to_string())?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.substrings.rules.is_empty() && self.regexes.rules.is_empty()
+    }
+
+    fn find(&self, content: &str, matched: &mut Vec<usize>) {
+        let substrings = &self.substrings;
+        matched.extend(
+            substrings
+                .matcher
+                .find_overlapping_iter(content)
+                .map(|m| substrings.rules[m.pattern().as_usize()]),
+        );
+
+        let regexes = &self.regexes;
+        matched.extend(
+            regexes
+                .matcher
+                .matches(content)
+                .into_iter()
+                .map(|ind| regexes.rules[ind]),
+        );
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "ContentConfig")]
 pub struct Content {
-    filters: Vec<String>,
+    filters: List<Vec<String>>,
+    configured_rules: Vec<ContentRule>,
+    normalize: Normalize,
+    reject_bidi_overrides: bool,
+
+    /// `filters` followed by `configured_rules`
+    rules: Vec<ContentRule>,
+    exact: Matchers,
+    folded: Matchers,
+}
+
+impl TryFrom<ContentConfig> for Content {
+    type Error = String;
+
+    fn try_from(config: ContentConfig) -> Result<Self, Self::Error> {
+        let normalize = config.normalize.unwrap_or_default();
+        let (rules, exact, folded) = compile(&config.filters, &config.rules, &normalize)?;
+
+        Ok(Content {
+            filters: config.filters,
+            configured_rules: config.rules,
+            normalize,
+            reject_bidi_overrides: config.reject_bidi_overrides,
+            rules,
+            exact,
+            folded,
+        })
+    }
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content::try_from(ContentConfig::default()).expect("empty content config")
+    }
+}
+
+/// Builds the matchers for the legacy `filters` substrings and the rules
+fn compile(
+    filters: &[String],
+    rules: &[ContentRule],
+    normalize: &Normalize,
+) -> Result<(Vec<ContentRule>, Matchers, Matchers), String> {
+    let legacy = filters.iter().map(|pattern| ContentRule {
+        pattern: pattern.clone(),
+        match_type: MatchType::Substring,
+        ignore_case: false,
+        action: RuleAction::ShadowReject,
+        message: None,
+    });
+    let rules: Vec<ContentRule> = legacy.chain(rules.iter().cloned()).collect();
+
+    let exact = Matchers::new(&rules, false, normalize)?;
+    let folded = Matchers::new(&rules, true, normalize)?;
+    Ok((rules, exact, folded))
+}
+
+impl Content {
+    /// Rebuilds the matchers if the `filters` list file changed
+    fn reload(&mut self) {
+        if !self.filters.reload() {
+            return;
+        }
+
+        match compile(&self.filters, &self.configured_rules, &self.normalize) {
+            Ok((rules, exact, folded)) => {
+                self.rules = rules;
+                self.exact = exact;
+                self.folded = folded;
+            }
+            Err(err) => error!("could not rebuild content rules: {}", err),
+        }
+    }
+
+    /// The strictest rule matching the content, earlier rules win ties
+    fn matching_rule(&self, content: &str) -> Option<&ContentRule> {
+        let mut matched: Vec<usize> = vec![];
+
+        self.exact
+            .find(&self.normalize.apply(content), &mut matched);
+
+        if !self.folded.is_empty() {
+            let content = self.normalize.apply_lowercase(content);
+            self.folded.find(&content, &mut matched);
+        }
+
+        matched
+            .into_iter()
+            .min_by_key(|&ind| (std::cmp::Reverse(self.rules[ind].action), ind))
+            .map(|ind| &self.rules[ind])
+    }
 }
 
 impl NoteFilter for Content {
     fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage {
-        for filter in &self.filters {
-            if msg.event.content.contains(filter) {
-                return OutputMessage::new(msg.event.id.clone(), Action::ShadowReject, None);
-            }
+        self.reload();
+        let id = msg.event.id.to_string();
+
+        if self.reject_bidi_overrides && msg.event.content.chars().any(is_bidi_control) {
+            return OutputMessage::new(
+                id,
+                Action::Reject,
+                Some("blocked: content contains bidi override characters".to_string()),
+            );
         }
 
-        OutputMessage::new(msg.event.id.clone(), Action::Accept, None)
+        let Some(rule) = self.matching_rule(&msg.event.content) else {
+            return OutputMessage::new(id, Action::Accept, None);
+        };
+
+        match rule.action {
+            RuleAction::Flag => {
+                info!(
+                    "content: flagged {}: {}",
+                    id,
+                    rule.message.as_deref().unwrap_or(&rule.pattern)
+                );
+                OutputMessage::new(id, Action::Accept, None)
+            }
+            RuleAction::ShadowReject => {
+                OutputMessage::new(id, Action::ShadowReject, rule.message.clone())
+            }
+            RuleAction::Reject => {
+                let message = rule
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| "blocked: content is not allowed".to_string());
+                OutputMessage::new(id, Action::Reject, Some(message))
+            }
+        }
     }
 
     fn name(&self) -> &'static str {
         "content"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input};
+
+    fn run(filter: &mut Content, content: &str) -> OutputMessage {
+        let mut input_message = mock_input();
+        input_message.event.content = content.to_string();
+        filter.filter_note(&input_message)
+    }
+
+    #[test]
+    fn test_content_rules() {
+        let mut filter: Content = load_filter(
+            r#"
+            filters = ["legacy spam"]
+
+            [[rules]]
+            pattern = "BUY FOLLOWERS"
+            ignore_case = true
+            action = "reject"
+            message = "blocked: no follower sales"
+
+            [[rules]]
+            pattern = "sol"
+            match = "word"
+            action = "flag"
+
+            [[rules]]
+            pattern = 'airdrop\s+\d+'
+            match = "regex"
+            action = "reject"
+
+            [[rules]]
+            pattern = "$SCAM"
+            match = "word"
+            action = "reject"
+        "#,
+        );
+
+        let output_message = run(&mut filter, "this is legacy spam");
+        assert_eq!(output_message.action, Action::ShadowReject);
+        assert_eq!(output_message.msg, None);
+
+        // the strictest matching rule wins
+        let output_message = run(&mut filter, "legacy spam: Buy Followers");
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: no follower sales")
+        );
+
+        let output_message = run(&mut filter, "claim your airdrop  500");
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: content is not allowed")
+        );
+
+        // flagged notes and partial words are accepted
+        assert_eq!(run(&mut filter, "sol is up").action, Action::Accept);
+        assert_eq!(run(&mut filter, "solar panels").action, Action::Accept);
+
+        // word patterns can start or end with non-word characters
+        assert_eq!(run(&mut filter, "$SCAM to the moon").action, Action::Reject);
+        assert_eq!(run(&mut filter, "buy ($SCAM)").action, Action::Reject);
+        assert_eq!(run(&mut filter, "$SCAMMER").action, Action::Accept);
+        assert_eq!(run(&mut filter, "US$SCAM").action, Action::Accept);
+
+        let invalid = toml::from_str::<Content>(
+            r#"
+            [[rules]]
+            pattern = "("
+            match = "regex"
+        "#,
+        );
+        assert!(invalid.is_err(), "invalid regex should fail");
+    }
+
+    #[test]
+    fn test_content_normalize() {
+        let mut filter: Content = load_filter(
+            r#"
+            reject_bidi_overrides = true
+
+            [normalize]
+            nfkc = true
+            strip_invisible = true
+            strip_marks = true
+            confusables = true
+
+            [[rules]]
+            pattern = "Free Money"
+            ignore_case = true
+            action = "reject"
+
+            [[rules]]
+            pattern = "scam"
+            match = "word"
+        "#,
+        );
+
+        // fullwidth letters, a zero-width space and a cyrillic o
+        let output_message = run(
+            &mut filter,
+            "\u{ff26}\u{ff32}\u{ff25}\u{ff25} m\u{200b}\u{043e}ney",
+        );
+        assert_eq!(output_message.action, Action::Reject);
+
+        // uppercase produced by nfkc is folded too
+        for content in ["𝐅𝐑𝐄𝐄 𝐌𝐎𝐍𝐄𝐘", "ℱℛℰℰ money"] {
+            assert_eq!(
+                run(&mut filter, content).action,
+                Action::Reject,
+                "{}",
+                content
+            );
+        }
+
+        // combining strikethrough marks
+        let output_message = run(&mut filter, "s\u{336}c\u{336}a\u{336}m\u{336}");
+        assert_eq!(output_message.action, Action::ShadowReject);
+
+        let output_message = run(&mut filter, "free m\u{202e}yenom");
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: content contains bidi override characters")
+        );
+
+        assert_eq!(run(&mut filter, "gm friends").action, Action::Accept);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::filters::Clock;
+use crate::{Action, InputMessage, Kind, NoteFilter, OutputMessage};
+use serde::Deserialize;
+
+/// Enforces [NIP-40] expiration tags
+///
+/// [NIP-40]: https://github.com/nostr-protocol/nips/blob/master/40.md
+#[derive(Deserialize, Default)]
+pub struct Expiration {
+    /// The furthest into the future a note may expire, in seconds
+    pub max_expiration: Option<u64>,
+
+    /// Kinds that must have an expiration tag
+    #[serde(default)]
+    pub required_kinds: Vec<Kind>,
+
+    #[serde(default)]
+    pub clock: Clock,
+}
+
+impl Expiration {
+    fn check(&self, input: &InputMessage) -> Result<(), String> {
+        let tags = &input.event.tags;
+        let expiration = match tags.expiration() {
+            Some(expiration) => i128::from(expiration),
+            None if tags.first_tag_value("expiration").is_some() => {
+                return Err("invalid: malformed expiration tag".to_string())
+            }
+            None if self.required_kinds.contains(&input.event.kind) => {
+                return Err(format!(
+                    "blocked: kind {} notes must have an expiration tag",
+                    input.event.kind
+                ))
+            }
+            None => return Ok(()),
+        };
+
+        let now = i128::from(self.clock.now(input));
+        if expiration <= now {
+            return Err("invalid: note has expired".to_string());
+        }
+
+        if let Some(max_expiration) = self.max_expiration {
+            if expiration - now > i128::from(max_expiration) {
+                return Err("invalid: expiration is too far in the future".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl NoteFilter for Expiration {
+    fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        match self.check(input) {
+            Ok(()) => OutputMessage::new(input.event.id.to_string(), Action::Accept, None),
+            Err(msg) => OutputMessage::new(input.event.id.to_string(), Action::Reject, Some(msg)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "expiration"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input, tags};
+
+    #[test]
+    fn test_expiration() {
+        let mut filter: Expiration = load_filter(
+            r#"
+            max_expiration = 3600
+            required_kinds = [30402]
+        "#,
+        );
+
+        let with_expiration = |expiration: &str| {
+            let mut input_message = mock_input();
+            input_message.received_at = 100000;
+            input_message.event.tags = tags(serde_json::json!([["expiration", expiration]]));
+            input_message
+        };
+
+        let output_message = filter.filter_note(&with_expiration("100600"));
+        assert_eq!(output_message.action, Action::Accept);
+
+        let output_message = filter.filter_note(&with_expiration("99999"));
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("invalid: note has expired")
+        );
+
+        let output_message = filter.filter_note(&with_expiration("200000"));
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("invalid: expiration is too far in the future")
+        );
+
+        for malformed in ["soon", "-1"] {
+            let output_message = filter.filter_note(&with_expiration(malformed));
+            assert_eq!(
+                output_message.msg.as_deref(),
+                Some("invalid: malformed expiration tag")
+            );
+        }
+
+        let output_message = filter.filter_note(&with_expiration(&u64::MAX.to_string()));
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("invalid: expiration is too far in the future")
+        );
+
+        // notes without an expiration are fine unless their kind requires one
+        assert_eq!(filter.filter_note(&mock_input()).action, Action::Accept);
+
+        let mut input_message = mock_input();
+        input_message.event.kind = Kind(30402);
+        let output_message = filter.filter_note(&input_message);
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: kind 30402 notes must have an expiration tag")
+        );
+    }
+}
@@ -84,6 +84,14 @@ impl NoteFilter for Forwarder {
         "forwarder"
     }
 
+    fn gauges(&self) -> Vec<(&'static str, u64)> {
+        let depth = self
+            .channel
+            .as_ref()
+            .map_or(0, |channel| channel.max_capacity() - channel.capacity());
+        vec![("forwarder_queue_depth", depth as u64)]
+    }
+
     fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
         if self.channel.is_none() {
             let (tx, rx) = mpsc::channel(self.queue_size.unwrap_or(1000) as usize);
@@ -104,6 +112,6 @@ impl NoteFilter for Forwarder {
         }
 
         // Create and return an appropriate OutputMessage
-        OutputMessage::new(input.event.id.clone(), Action::Accept, None)
+        OutputMessage::new(input.event.id.to_string(), Action::Accept, None)
     }
 }
@@ -1,25 +1,189 @@
-use crate::{Action, InputMessage, NoteFilter, OutputMessage};
-use serde::Deserialize;
-use std::collections::HashMap;
+use crate::filters::RuleAction;
+use crate::{Action, InputMessage, Kind, KindClass, List, NoteFilter, OutputMessage};
+use log::info;
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
+
+/// A single kind, an inclusive range like `"20000-29999"`, or a NIP-01
+/// class name like `"ephemeral"`
+#[derive(Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(try_from = "KindSpecValue")]
+pub enum KindSpec {
+    Kind(Kind),
+    Range(Kind, Kind),
+    Class(KindClass),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KindSpecValue {
+    Kind(u16),
+    Str(String),
+}
+
+impl TryFrom<KindSpecValue> for KindSpec {
+    type Error = String;
+
+    fn try_from(value: KindSpecValue) -> Result<Self, Self::Error> {
+        let s = match value {
+            KindSpecValue::Kind(kind) => return Ok(KindSpec::Kind(Kind(kind))),
+            KindSpecValue::Str(s) => s,
+        };
+
+        let class = match s.as_str() {
+            "regular" => Some(KindClass::Regular),
+            "replaceable" => Some(KindClass::Replaceable),
+            "ephemeral" => Some(KindClass::Ephemeral),
+            "addressable" => Some(KindClass::Addressable),
+            _ => None,
+        };
+        if let Some(class) = class {
+            return Ok(KindSpec::Class(class));
+        }
+
+        let kind = |s: &str| s.trim().parse::<u16>().ok().map(Kind);
+        let spec = match s.split_once('-') {
+            Some((start, end)) => kind(start)
+                .zip(kind(end))
+                .filter(|(start, end)| start <= end)
+                .map(|(start, end)| KindSpec::Range(start, end)),
+            None => kind(&s).map(KindSpec::Kind),
+        };
+
+        spec.ok_or_else(|| {
+            format!(
+                "invalid kind '{}', expected a kind, a range like '20000-29999', \
+                 or one of regular, replaceable, ephemeral or addressable",
+                s
+            )
+        })
+    }
+}
+
+impl KindSpec {
+    pub fn matches(&self, kind: Kind) -> bool {
+        match *self {
+            KindSpec::Kind(k) => k == kind,
+            KindSpec::Range(start, end) => start <= kind && kind <= end,
+            KindSpec::Class(class) => kind.class() == class,
+        }
+    }
+}
+
+/// A set of kinds, kind ranges and kind classes
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(from = "Vec<KindSpec>")]
+pub struct KindSet {
+    kinds: HashSet<Kind>,
+    others: Vec<KindSpec>,
+}
+
+impl From<Vec<KindSpec>> for KindSet {
+    fn from(specs: Vec<KindSpec>) -> Self {
+        let mut set = KindSet::default();
+        for spec in specs {
+            match spec {
+                KindSpec::Kind(kind) => {
+                    set.kinds.insert(kind);
+                }
+                _ => set.others.push(spec),
+            }
+        }
+        set
+    }
+}
+
+impl KindSet {
+    pub fn contains(&self, kind: Kind) -> bool {
+        self.kinds.contains(&kind) || self.others.iter().any(|spec| spec.matches(kind))
+    }
+}
+
+/// `messages` and `actions` are set per kind. Ranges and classes would be
+/// ambiguous when they overlap, so they are rejected instead of ignored.
+fn deserialize_per_kind<'de, D, V>(deserializer: D) -> Result<Option<HashMap<Kind, V>>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    let Some(values) = Option::<HashMap<String, V>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    values
+        .into_iter()
+        .map(|(key, value)| match key.trim().parse::<u16>() {
+            Ok(kind) => Ok((Kind(kind), value)),
+            Err(_) => Err(serde::de::Error::custom(format!(
+                "'{}' is not a kind, messages and actions are set for single kinds",
+                key
+            ))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
 
 #[derive(Deserialize, Default)]
 pub struct Kinds {
-    kinds: Vec<i64>,
-    messages: Option<HashMap<String, String>>,
+    /// Kinds that are blocked
+    #[serde(default)]
+    kinds: List<KindSet>,
+
+    /// If set, only these kinds are accepted
+    allow: Option<List<KindSet>>,
+
+    #[serde(default, deserialize_with = "deserialize_per_kind")]
+    messages: Option<HashMap<Kind, String>>,
+
+    /// What to do with blocked notes of each kind, rejecting by default
+    #[serde(default, deserialize_with = "deserialize_per_kind")]
+    actions: Option<HashMap<Kind, RuleAction>>,
+}
+
+impl Kinds {
+    fn is_blocked(&self, kind: Kind) -> bool {
+        self.kinds.contains(kind)
+            || self
+                .allow
+                .as_ref()
+                .is_some_and(|allow| !allow.contains(kind))
+    }
 }
 
 impl NoteFilter for Kinds {
     fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        self.kinds.reload();
+        if let Some(allow) = &mut self.allow {
+            allow.reload();
+        }
+
+        let id = input.event.id.to_string();
         let kind = input.event.kind;
-        if self.kinds.contains(&kind) {
-            let msg = self
-                .messages
-                .as_ref()
-                .and_then(|msgs| msgs.get(&kind.to_string()).cloned())
-                .unwrap_or_else(|| "blocked: note kind is not allowed here".to_string());
-            OutputMessage::new(input.event.id.clone(), Action::Reject, Some(msg))
-        } else {
-            OutputMessage::new(input.event.id.clone(), Action::Accept, None)
+        if !self.is_blocked(kind) {
+            return OutputMessage::new(id, Action::Accept, None);
+        }
+
+        let msg = self
+            .messages
+            .as_ref()
+            .and_then(|msgs| msgs.get(&kind).cloned());
+        let action = self
+            .actions
+            .as_ref()
+            .and_then(|actions| actions.get(&kind).copied())
+            .unwrap_or(RuleAction::Reject);
+
+        match action {
+            RuleAction::Flag => {
+                info!("kinds: flagged {}: kind {}", id, kind);
+                OutputMessage::new(id, Action::Accept, None)
+            }
+            RuleAction::ShadowReject => OutputMessage::new(id, Action::ShadowReject, msg),
+            RuleAction::Reject => {
+                let msg =
+                    msg.unwrap_or_else(|| "blocked: note kind is not allowed here".to_string());
+                OutputMessage::new(id, Action::Reject, Some(msg))
+            }
         }
     }
 
@@ -27,3 +191,62 @@ impl NoteFilter for Kinds {
         "kinds"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input};
+
+    fn run(filter: &mut Kinds, kind: u16) -> OutputMessage {
+        let mut input_message = mock_input();
+        input_message.event.kind = Kind(kind);
+        filter.filter_note(&input_message)
+    }
+
+    #[test]
+    fn test_kinds_allow() {
+        let mut filter: Kinds = load_filter(
+            r#"
+            allow = [0, 1, 3, "7", "10000-19999", "ephemeral"]
+            kinds = [10063]
+
+            [actions]
+            4 = "shadowReject"
+
+            [messages]
+            30023 = "blocked: no long-form here"
+        "#,
+        );
+
+        for kind in [1, 10002, 24133] {
+            assert_eq!(run(&mut filter, kind).action, Action::Accept);
+        }
+
+        // blocked kinds win over allowed ranges
+        assert_eq!(run(&mut filter, 10063).action, Action::Reject);
+        assert_eq!(run(&mut filter, 4).action, Action::ShadowReject);
+
+        let output_message = run(&mut filter, 30023);
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: no long-form here")
+        );
+
+        let err = toml::from_str::<Kinds>(r#"allow = ["29999-20000"]"#)
+            .err()
+            .expect("backwards range should fail");
+        assert!(err.to_string().contains("invalid kind '29999-20000'"));
+
+        let err = toml::from_str::<Kinds>(
+            r#"
+            kinds = ["ephemeral"]
+            [messages]
+            ephemeral = "blocked: no ephemeral notes"
+        "#,
+        )
+        .err()
+        .expect("class message should fail");
+        assert!(err.to_string().contains("'ephemeral' is not a kind"));
+    }
+}
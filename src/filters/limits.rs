@@ -0,0 +1,172 @@
+use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Size caps on a note. Unset caps fall back to the filter wide setting.
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+pub struct SizeLimits {
+    /// Maximum content length in bytes
+    pub max_content_bytes: Option<usize>,
+
+    /// Maximum number of tags
+    pub max_tags: Option<usize>,
+
+    /// Maximum number of elements in a single tag, including its name
+    pub max_tag_elements: Option<usize>,
+
+    /// Maximum length in bytes of any tag element
+    pub max_tag_value_bytes: Option<usize>,
+
+    /// Maximum length in bytes of the whole note serialized as json
+    pub max_event_bytes: Option<usize>,
+
+    /// Maximum length in bytes of the `d` tag identifier
+    pub max_d_tag_bytes: Option<usize>,
+}
+
+impl SizeLimits {
+    fn or(self, other: SizeLimits) -> SizeLimits {
+        SizeLimits {
+            max_content_bytes: self.max_content_bytes.or(other.max_content_bytes),
+            max_tags: self.max_tags.or(other.max_tags),
+            max_tag_elements: self.max_tag_elements.or(other.max_tag_elements),
+            max_tag_value_bytes: self.max_tag_value_bytes.or(other.max_tag_value_bytes),
+            max_event_bytes: self.max_event_bytes.or(other.max_event_bytes),
+            max_d_tag_bytes: self.max_d_tag_bytes.or(other.max_d_tag_bytes),
+        }
+    }
+
+    fn check(&self, input: &InputMessage) -> Result<(), String> {
+        let note = &input.event;
+
+        if let Some(max) = self.max_content_bytes {
+            if note.content.len() > max {
+                return Err(format!("blocked: content is longer than {} bytes", max));
+            }
+        }
+
+        if let Some(max) = self.max_tags {
+            if note.tags.len() > max {
+                return Err(format!("blocked: note has more than {} tags", max));
+            }
+        }
+
+        if let Some(max) = self.max_tag_elements {
+            if note.tags.iter().any(|tag| tag.len() > max) {
+                return Err(format!("blocked: a tag has more than {} elements", max));
+            }
+        }
+
+        if let Some(max) = self.max_tag_value_bytes {
+            if note
+                .tags
+                .iter()
+                .any(|tag| tag.iter().any(|value| value.len() > max))
+            {
+                return Err(format!("blocked: a tag value is longer than {} bytes", max));
+            }
+        }
+
+        if let Some(max) = self.max_d_tag_bytes {
+            if note
+                .tags
+                .first_tag_value("d")
+                .is_some_and(|d| d.len() > max)
+            {
+                return Err(format!("blocked: d tag is longer than {} bytes", max));
+            }
+        }
+
+        if let Some(max) = self.max_event_bytes {
+            let size = serde_json::to_vec(note).map_or(0, |json| json.len());
+            if size > max {
+                return Err(format!("blocked: note is larger than {} bytes", max));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Default)]
+// flattened settings are invisible to the unknown setting check
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    #[serde(flatten)]
+    pub limits: SizeLimits,
+
+    /// Limits for specific kinds
+    pub kinds: Option<HashMap<String, SizeLimits>>,
+}
+
+impl NoteFilter for Limits {
+    fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        let limits = match self
+            .kinds
+            .as_ref()
+            .and_then(|kinds| kinds.get(&input.event.kind.to_string()))
+        {
+            Some(kind_limits) => kind_limits.or(self.limits),
+            None => self.limits,
+        };
+
+        match limits.check(input) {
+            Ok(()) => OutputMessage::new(input.event.id.to_string(), Action::Accept, None),
+            Err(msg) => OutputMessage::new(input.event.id.to_string(), Action::Reject, Some(msg)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "limits"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input, tags};
+    use crate::Kind;
+
+    #[test]
+    fn test_limits() {
+        let mut filter: Limits = load_filter(
+            r#"
+            max_content_bytes = 10
+            max_tags = 2
+            max_d_tag_bytes = 8
+
+            [kinds.30023]
+            max_content_bytes = 100
+        "#,
+        );
+
+        let mut input_message = mock_input();
+        input_message.event.content = "hello nostr".to_string();
+        let output_message = filter.filter_note(&input_message);
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: content is longer than 10 bytes")
+        );
+
+        // long-form notes can be longer but still share the other limits
+        input_message.event.kind = Kind(30023);
+        assert_eq!(filter.filter_note(&input_message).action, Action::Accept);
+
+        input_message.event.tags = tags(serde_json::json!([["d", "a-very-long-identifier"]]));
+        let output_message = filter.filter_note(&input_message);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: d tag is longer than 8 bytes")
+        );
+
+        let mut input_message = mock_input();
+        input_message.event.content = "hi".to_string();
+        input_message.event.tags = tags(serde_json::json!([["t", "a"], ["t", "b"], ["t", "c"]]));
+        let output_message = filter.filter_note(&input_message);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: note has more than 2 tags")
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::filters::{note_urls, RuleAction};
+use crate::{Action, InputMessage, Kind, List, Note, NoteFilter, OutputMessage};
+use hex::FromHex;
+use log::info;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+use std::fmt;
+
+/// NIP-94 file metadata
+const FILE_METADATA: Kind = Kind(1063);
+
+/// The sha256 hash of a file, written as hex
+#[derive(Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[serde(try_from = "String")]
+pub struct FileHash(pub [u8; 32]);
+
+impl TryFrom<String> for FileHash {
+    type Error = String;
+
+    fn try_from(hash: String) -> Result<Self, Self::Error> {
+        <[u8; 32]>::from_hex(&hash)
+            .map(FileHash)
+            .map_err(|_| format!("'{}' is not a sha256 hash", hash))
+    }
+}
+
+impl fmt::Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+fn parse_hash(hash: &str) -> Option<FileHash> {
+    <[u8; 32]>::from_hex(hash).ok().map(FileHash)
+}
+
+/// The sha256 hashes of files referenced by a note: `x` and `ox` tags on
+/// file metadata notes, `x` and `ox` entries in `imeta` tags, and blossom
+/// style urls, which are named after the hash of the file they serve.
+pub fn media_hashes(note: &Note) -> HashSet<FileHash> {
+    let mut hashes = HashSet::new();
+
+    for tag in note.tags.iter() {
+        match tag.name() {
+            Some("x") | Some("ox") if note.kind == FILE_METADATA => {
+                hashes.extend(tag.value().and_then(parse_hash));
+            }
+            Some("imeta") => hashes.extend(tag.iter().skip(1).filter_map(|entry| {
+                let hash = entry
+                    .strip_prefix("x ")
+                    .or_else(|| entry.strip_prefix("ox "))?;
+                parse_hash(hash)
+            })),
+            _ => {}
+        }
+    }
+
+    for url in note_urls(note) {
+        let file = url.rsplit('/').next().unwrap_or_default();
+        let name = file.split(['.', '?', '#']).next().unwrap_or_default();
+        if name.len() == 64 {
+            hashes.extend(parse_hash(name));
+        }
+    }
+
+    hashes
+}
+
+/// Hash blocklists are usually too long to write inline, so besides the usual
+/// list forms a plain string is read as the path of a list file
+fn deserialize_blocklist<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<List<HashSet<FileHash>>, D::Error> {
+    match toml::Value::deserialize(deserializer)? {
+        toml::Value::String(path) => List::from_file(path).map_err(D::Error::custom),
+        value => List::deserialize(value).map_err(D::Error::custom),
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct MediaHashes {
+    /// Blocked hashes, usually a list file
+    #[serde(deserialize_with = "deserialize_blocklist")]
+    pub blocklist: List<HashSet<FileHash>>,
+
+    #[serde(default)]
+    pub action: RuleAction,
+
+    pub message: Option<String>,
+}
+
+impl NoteFilter for MediaHashes {
+    fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        self.blocklist.reload();
+
+        let id = input.event.id.to_string();
+        let blocked = media_hashes(&input.event)
+            .into_iter()
+            .find(|hash| self.blocklist.contains(hash));
+
+        let Some(hash) = blocked else {
+            return OutputMessage::new(id, Action::Accept, None);
+        };
+
+        match self.action {
+            RuleAction::Flag => {
+                info!("media_hashes: flagged {}: {}", id, hash);
+                OutputMessage::new(id, Action::Accept, None)
+            }
+            RuleAction::ShadowReject => {
+                OutputMessage::new(id, Action::ShadowReject, self.message.clone())
+            }
+            RuleAction::Reject => {
+                let message = self
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| "blocked: this media is not allowed".to_string());
+                OutputMessage::new(id, Action::Reject, Some(message))
+            }
+        }
+    }
+
+    fn gauges(&self) -> Vec<(&'static str, u64)> {
+        vec![("media_hashes_blocked", self.blocklist.len() as u64)]
+    }
+
+    fn name(&self) -> &'static str {
+        "media_hashes"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input, tags};
+
+    #[test]
+    fn test_media_hashes() {
+        let blocked = "b1674191a88ec5cdd733e4240a81803105dc412d6c6708d53ab94fc248f4f553";
+        let path = std::env::temp_dir().join(format!("noteguard-media-{}", std::process::id()));
+        std::fs::write(&path, format!("# abusive images\n{}\n", blocked))
+            .expect("Failed to write blocklist");
+
+        let mut filter: MediaHashes = load_filter(&format!(
+            r#"
+            blocklist = "{}"
+            action = "shadowReject"
+        "#,
+            path.display()
+        ));
+
+        let run = |filter: &mut MediaHashes, kind: u16, content: &str, note_tags| {
+            let mut input_message = mock_input();
+            input_message.event.kind = Kind(kind);
+            input_message.event.content = content.to_string();
+            input_message.event.tags = tags(note_tags);
+            filter.filter_note(&input_message).action
+        };
+
+        let action = run(
+            &mut filter,
+            1063,
+            "",
+            serde_json::json!([["x", blocked], ["m", "image/png"]]),
+        );
+        assert_eq!(action, Action::ShadowReject);
+
+        let action = run(
+            &mut filter,
+            1,
+            "look",
+            serde_json::json!([[
+                "imeta",
+                "url https://example.com/a.png",
+                format!("x {}", blocked)
+            ]]),
+        );
+        assert_eq!(action, Action::ShadowReject);
+
+        let action = run(
+            &mut filter,
+            1,
+            &format!("rehosted https://blossom.example/{}.png", blocked),
+            serde_json::json!([]),
+        );
+        assert_eq!(action, Action::ShadowReject);
+
+        // x tags only mean a file hash on file metadata notes
+        let action = run(&mut filter, 1, "", serde_json::json!([["x", blocked]]));
+        assert_eq!(action, Action::Accept);
+
+        // like other list files, a bad line rejects the whole file
+        std::fs::write(&path, format!("{}\nnot-a-hash\n", blocked))
+            .expect("Failed to write blocklist");
+        let err = toml::from_str::<MediaHashes>(&format!("blocklist = '{}'", path.display()))
+            .err()
+            .expect("invalid hash should fail");
+        assert!(
+            err.to_string()
+                .contains(":2: 'not-a-hash' is not a sha256 hash"),
+            "{}",
+            err
+        );
+
+        std::fs::remove_file(&path).expect("Failed to remove blocklist");
+
+        let filter: MediaHashes = load_filter(&format!("blocklist = ['{}']", blocked));
+        assert_eq!(filter.gauges(), vec![("media_hashes_blocked", 1)]);
+    }
+}
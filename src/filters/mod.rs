@@ -1,18 +1,37 @@
 mod blacklist;
+mod combinators;
 mod content;
+mod expiration;
 mod kinds;
+mod limits;
+mod media_hashes;
+mod normalize;
+mod pow;
 mod protected_events;
 mod ratelimit;
+mod timestamps;
+mod urls;
+mod verify;
 mod whitelist;
 
 #[cfg(feature = "forwarder")]
 mod forwarder;
 
 pub use blacklist::Blacklist;
-pub use content::Content;
-pub use kinds::Kinds;
+pub use combinators::{Combinator, CombinatorConfig, CombinatorKind};
+pub use content::{Content, ContentConfig, ContentRule, MatchType, RuleAction};
+pub use expiration::Expiration;
+pub use kinds::{KindSpec, Kinds};
+pub use limits::{Limits, SizeLimits};
+pub use media_hashes::{media_hashes, FileHash, MediaHashes};
+pub use normalize::{is_bidi_control, is_invisible, Normalize};
+pub use pow::Pow;
 pub use protected_events::ProtectedEvents;
 pub use ratelimit::RateLimit;
+pub(crate) use ratelimit::{SharesThrottled, Throttled};
+pub use timestamps::{Bounds, Clock, Timestamps};
+pub use urls::{normalize_host, note_urls, DomainSet, Urls};
+pub use verify::{verify_note, Verify, VerifyError};
 pub use whitelist::Whitelist;
 
 #[cfg(feature = "forwarder")]
@@ -0,0 +1,97 @@
+use serde::Deserialize;
+use std::borrow::Cow;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::skeleton;
+
+/// Bidi embedding, override and isolate controls, which can make text
+/// display in a different order than it is stored
+pub fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Characters that have no visible glyph, like zero-width joiners,
+/// bidi marks and soft hyphens
+pub fn is_invisible(c: char) -> bool {
+    is_bidi_control(c)
+        || matches!(
+            c,
+            '\u{00ad}'
+                | '\u{034f}'
+                | '\u{061c}'
+                | '\u{115f}'
+                | '\u{1160}'
+                | '\u{17b4}'
+                | '\u{17b5}'
+                | '\u{180b}'..='\u{180f}'
+                | '\u{200b}'..='\u{200f}'
+                | '\u{2060}'..='\u{2064}'
+                | '\u{3164}'
+                | '\u{fe00}'..='\u{fe0f}'
+                | '\u{feff}'
+                | '\u{ffa0}'
+                | '\u{e0000}'..='\u{e0fff}'
+        )
+}
+
+/// Ways of folding text before matching, so lookalike spellings of a
+/// pattern still match it
+#[derive(Deserialize, Default, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Normalize {
+    /// Apply NFKC, folding fullwidth, circled, superscript letters and
+    /// the like into their plain forms
+    #[serde(default)]
+    pub nfkc: bool,
+
+    /// Remove zero-width, bidi control and other invisible characters
+    #[serde(default)]
+    pub strip_invisible: bool,
+
+    /// Remove combining marks, like accents and strikethrough overlays
+    #[serde(default)]
+    pub strip_marks: bool,
+
+    /// Map confusable characters, like Cyrillic lookalikes of Latin
+    /// letters, to their Unicode TR39 skeleton
+    #[serde(default)]
+    pub confusables: bool,
+}
+
+impl Normalize {
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.fold(text, false)
+    }
+
+    /// Like [`Normalize::apply`], but also lowercases the text. This
+    /// happens after NFKC, so uppercase letters it produces are folded
+    /// too, and before confusables, so both cases map to one skeleton.
+    pub fn apply_lowercase(&self, text: &str) -> String {
+        self.fold(text, true).into_owned()
+    }
+
+    fn fold<'a>(&self, text: &'a str, lowercase: bool) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+
+        if self.nfkc {
+            text = Cow::Owned(text.nfkc().collect());
+        }
+
+        if self.strip_invisible && text.chars().any(is_invisible) {
+            text = Cow::Owned(text.chars().filter(|&c| !is_invisible(c)).collect());
+        }
+
+        if self.strip_marks {
+            text = Cow::Owned(text.nfd().filter(|&c| !is_combining_mark(c)).collect());
+        }
+
+        if lowercase {
+            text = Cow::Owned(text.to_lowercase());
+        }
+
+        if self.confusables {
+            text = Cow::Owned(skeleton(&text).collect());
+        }
+
+        text
+    }
+}
@@ -0,0 +1,207 @@
+use super::ratelimit::{SharesThrottled, Throttled};
+use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn default_throttled_window() -> u64 {
+    600
+}
+
+/// Requires [NIP-13] proof of work on notes
+///
+/// [NIP-13]: https://github.com/nostr-protocol/nips/blob/master/13.md
+#[derive(Deserialize)]
+pub struct Pow {
+    /// The minimum number of leading zero bits in the note id
+    #[serde(default)]
+    pub difficulty: u32,
+
+    /// Difficulty for specific kinds, these take precedence over `source_types`
+    pub kinds: Option<HashMap<String, u32>>,
+
+    /// Difficulty for specific strfry source types
+    pub source_types: Option<HashMap<String, u32>>,
+
+    /// Also require the target committed to in the `nonce` tag to be at
+    /// least the required difficulty, so notes that got lucky don't count
+    #[serde(default)]
+    pub check_nonce: bool,
+
+    /// Difficulty for ips and pubkeys that a ratelimit has recently throttled
+    pub throttled_difficulty: Option<u32>,
+
+    /// How long, in seconds, `throttled_difficulty` applies after being throttled
+    #[serde(default = "default_throttled_window")]
+    pub throttled_window: u64,
+
+    #[serde(skip)]
+    throttled: Throttled,
+}
+
+impl Default for Pow {
+    fn default() -> Self {
+        Pow {
+            difficulty: 0,
+            kinds: None,
+            source_types: None,
+            check_nonce: false,
+            throttled_difficulty: None,
+            throttled_window: default_throttled_window(),
+            throttled: Throttled::default(),
+        }
+    }
+}
+
+impl Pow {
+    fn required_difficulty(&self, input: &InputMessage) -> u32 {
+        let kind = self
+            .kinds
+            .as_ref()
+            .and_then(|kinds| kinds.get(&input.event.kind.to_string()));
+        let source_type = self
+            .source_types
+            .as_ref()
+            .and_then(|source_types| source_types.get(&input.source_type));
+        let difficulty = kind.or(source_type).copied().unwrap_or(self.difficulty);
+
+        match self.throttled_difficulty {
+            Some(throttled_difficulty) if self.is_throttled(input) => {
+                difficulty.max(throttled_difficulty)
+            }
+            _ => difficulty,
+        }
+    }
+
+    fn is_throttled(&self, input: &InputMessage) -> bool {
+        let window = Duration::from_secs(self.throttled_window);
+        self.throttled.contains(&input.source_info, window)
+            || self
+                .throttled
+                .contains(&input.event.pubkey.to_hex(), window)
+    }
+
+    fn check(&self, input: &InputMessage) -> Result<(), String> {
+        let required = self.required_difficulty(input);
+        if required == 0 {
+            return Ok(());
+        }
+
+        let difficulty = input.event.id.difficulty();
+        if difficulty < required {
+            return Err(format!(
+                "pow: difficulty {} is less than {}",
+                difficulty, required
+            ));
+        }
+
+        if self.check_nonce {
+            let target = input
+                .event
+                .tags
+                .tags_named("nonce")
+                .next()
+                .and_then(|nonce| nonce.get(2))
+                .and_then(|target| target.parse::<u32>().ok());
+
+            match target {
+                None => return Err("pow: missing committed target in nonce tag".to_string()),
+                Some(target) if target < required => {
+                    return Err(format!(
+                        "pow: committed target {} is less than {}",
+                        target, required
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SharesThrottled for Pow {
+    fn set_throttled(&mut self, throttled: &Throttled) {
+        self.throttled = throttled.clone();
+    }
+}
+
+impl NoteFilter for Pow {
+    fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        match self.check(input) {
+            Ok(()) => OutputMessage::new(input.event.id.to_string(), Action::Accept, None),
+            Err(msg) => OutputMessage::new(input.event.id.to_string(), Action::Reject, Some(msg)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "pow"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::RateLimit;
+    use crate::test_utils::{load_filter, mock_input, tags};
+    use crate::{Kind, NoteId};
+
+    #[test]
+    fn test_pow() {
+        let mut filter: Pow = load_filter(
+            r#"
+            difficulty = 8
+            check_nonce = true
+            throttled_difficulty = 16
+
+            [kinds]
+            7 = 0
+        "#,
+        );
+
+        // an id with 12 leading zero bits, committing to a target
+        let mined = |target: &str| {
+            let mut id = [0xff; 32];
+            id[0] = 0;
+            id[1] = 0x0f;
+            let mut input_message = mock_input();
+            input_message.event.id = NoteId::from_bytes(id);
+            input_message.event.tags = tags(serde_json::json!([["nonce", "776797", target]]));
+            input_message
+        };
+
+        assert_eq!(filter.filter_note(&mined("12")).action, Action::Accept);
+
+        let output_message = filter.filter_note(&mined("4"));
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("pow: committed target 4 is less than 8")
+        );
+
+        let mut input_message = mock_input();
+        input_message.event.id = NoteId::from_bytes([0xff; 32]);
+        let output_message = filter.filter_note(&input_message);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("pow: difficulty 0 is less than 8")
+        );
+
+        input_message.event.kind = Kind(7);
+        assert_eq!(filter.filter_note(&input_message).action, Action::Accept);
+
+        // once the ratelimit throttles an ip it needs the stricter difficulty
+        let throttled = Throttled::default();
+        filter.set_throttled(&throttled);
+        let mut ratelimit: RateLimit = load_filter("posts_per_minute = 1");
+        ratelimit.set_throttled(&throttled);
+        ratelimit.filter_note(&mined("12"));
+        assert_eq!(ratelimit.filter_note(&mined("12")).action, Action::Reject);
+
+        let output_message = filter.filter_note(&mined("12"));
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("pow: difficulty 12 is less than 16")
+        );
+    }
+}
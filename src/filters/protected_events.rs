@@ -6,19 +6,15 @@ pub struct ProtectedEvents {}
 
 impl NoteFilter for ProtectedEvents {
     fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
-        if let Some(tag) = input.event.tags.first() {
-            if let Some(entry) = tag.first() {
-                if entry == "-" {
-                    return OutputMessage::new(
-                        input.event.id.clone(),
-                        Action::Reject,
-                        Some("blocked: event marked as protected".to_string()),
-                    );
-                }
-            }
+        if input.event.tags.has_tag("-") {
+            return OutputMessage::new(
+                input.event.id.to_string(),
+                Action::Reject,
+                Some("blocked: event marked as protected".to_string()),
+            );
         }
 
-        OutputMessage::new(input.event.id.clone(), Action::Accept, None)
+        OutputMessage::new(input.event.id.to_string(), Action::Accept, None)
     }
 
     fn name(&self) -> &'static str {
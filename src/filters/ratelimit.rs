@@ -1,8 +1,61 @@
-use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use crate::{Action, InputMessage, IpSet, List, NoteFilter, OutputMessage};
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+/// How long a throttled source or pubkey is remembered for other filters
+pub(crate) const THROTTLE_MEMORY: Duration = Duration::from_secs(60 * 60);
+
+/// How often forgotten sources and pubkeys are pruned
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct ThrottledState {
+    keys: HashMap<String, Instant>,
+    last_prune: Option<Instant>,
+}
+
+/// Sources and pubkeys that a ratelimit has recently rejected. Each
+/// [`crate::Noteguard`] hands one of these to the builtin filters that share
+/// it when they are built, so other filters like `pow` can be stricter with
+/// them.
+#[derive(Clone, Default)]
+pub(crate) struct Throttled(Arc<Mutex<ThrottledState>>);
+
+impl Throttled {
+    fn state(&self) -> std::sync::MutexGuard<'_, ThrottledState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn record(&self, keys: &[&str]) {
+        let mut state = self.state();
+        let now = Instant::now();
+
+        if state.last_prune.is_none_or(|at| now - at >= PRUNE_INTERVAL) {
+            state.keys.retain(|_, at| now - *at < THROTTLE_MEMORY);
+            state.last_prune = Some(now);
+        }
+
+        for key in keys {
+            state.keys.insert(key.to_string(), now);
+        }
+    }
+
+    /// Whether this source ip or hex pubkey was throttled within `window`
+    pub fn contains(&self, key: &str, window: Duration) -> bool {
+        self.state()
+            .keys
+            .get(key)
+            .is_some_and(|at| at.elapsed() < window.min(THROTTLE_MEMORY))
+    }
+}
+
+/// Builtin filters that share their noteguard's [`Throttled`]
+pub(crate) trait SharesThrottled {
+    fn set_throttled(&mut self, throttled: &Throttled);
+}
+
 pub struct Tokens {
     pub tokens: i32,
     pub last_post: Instant,
@@ -11,11 +64,24 @@ pub struct Tokens {
 #[derive(Deserialize, Default)]
 pub struct RateLimit {
     pub posts_per_minute: i32,
-    pub whitelist: Option<Vec<String>>,
+    pub whitelist: Option<List<IpSet>>,
     pub message: Option<String>,
 
+    /// Source types that are never rate-limited, eg: `Import` or `Sync`
+    #[serde(default)]
+    pub exempt_source_types: Vec<String>,
+
     #[serde(skip)]
     pub sources: HashMap<String, Tokens>,
+
+    #[serde(skip)]
+    throttled: Throttled,
+}
+
+impl SharesThrottled for RateLimit {
+    fn set_throttled(&mut self, throttled: &Throttled) {
+        self.throttled = throttled.clone();
+    }
 }
 
 impl NoteFilter for RateLimit {
@@ -23,11 +89,50 @@ impl NoteFilter for RateLimit {
         "ratelimit"
     }
 
+    fn gauges(&self) -> Vec<(&'static str, u64)> {
+        vec![("ratelimit_buckets", self.sources.len() as u64)]
+    }
+
+    fn observe(&mut self, msg: &InputMessage) {
+        self.take_token(msg);
+    }
+
     fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage {
-        if let Some(whitelist) = &self.whitelist {
-            if whitelist.contains(&msg.source_info) {
-                return OutputMessage::new(msg.event.id.clone(), Action::Accept, None);
-            }
+        if self.take_token(msg) {
+            return OutputMessage::new(msg.event.id.to_string(), Action::Accept, None);
+        }
+
+        self.throttled
+            .record(&[&msg.source_info, &msg.event.pubkey.to_hex()]);
+
+        let message = self
+            .message
+            .as_deref()
+            .unwrap_or("rate-limited: you are noting too much");
+
+        OutputMessage::new(
+            msg.event.id.to_string(),
+            Action::Reject,
+            Some(message.to_owned()),
+        )
+    }
+}
+
+impl RateLimit {
+    /// Spend one of the source's tokens, false if it has none left
+    fn take_token(&mut self, msg: &InputMessage) -> bool {
+        if let Some(whitelist) = &mut self.whitelist {
+            whitelist.reload();
+        }
+
+        let exempt = self.exempt_source_types.contains(&msg.source_type)
+            || msg.source_ip().is_some_and(|addr| {
+                self.whitelist
+                    .as_ref()
+                    .is_some_and(|whitelist| whitelist.contains(addr))
+            });
+        if exempt {
+            return true;
         }
 
         if !self.sources.contains_key(&msg.source_info) {
@@ -38,7 +143,7 @@ impl NoteFilter for RateLimit {
                     tokens: self.posts_per_minute,
                 },
             );
-            return OutputMessage::new(msg.event.id.clone(), Action::Accept, None);
+            return true;
         }
 
         let entry = self.sources.get_mut(&msg.source_info).expect("impossiburu");
@@ -63,19 +168,80 @@ impl NoteFilter for RateLimit {
         }
 
         if entry.tokens == 0 {
-            let message = self
-                .message
-                .as_deref()
-                .unwrap_or("rate-limited: you are noting too much");
-
-            return OutputMessage::new(
-                msg.event.id.clone(),
-                Action::Reject,
-                Some(message.to_owned()),
-            );
+            return false;
         }
 
         entry.last_post = now;
-        OutputMessage::new(msg.event.id.clone(), Action::Accept, None)
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input};
+
+    #[test]
+    fn test_ratelimit() {
+        let mut filter: RateLimit = load_filter(
+            r#"
+            posts_per_minute = 1
+            whitelist = ["198.51.100.0/24"]
+            exempt_source_types = ["Sync"]
+        "#,
+        );
+
+        let run = |filter: &mut RateLimit, source_type: &str, source_info: &str| {
+            let mut input_message = mock_input();
+            input_message.source_type = source_type.to_string();
+            input_message.source_info = source_info.to_string();
+            filter.filter_note(&input_message).action
+        };
+
+        for _ in 0..2 {
+            assert_eq!(run(&mut filter, "IP4", "198.51.100.20"), Action::Accept);
+            assert_eq!(
+                run(&mut filter, "Sync", "wss://relay.example"),
+                Action::Accept
+            );
+        }
+
+        assert_eq!(run(&mut filter, "IP4", "192.0.2.1"), Action::Accept);
+        assert_eq!(run(&mut filter, "IP4", "192.0.2.1"), Action::Reject);
+
+        // other source types are rate-limited unless they are exempt
+        assert_eq!(
+            run(&mut filter, "Stream", "wss://relay.example"),
+            Action::Accept
+        );
+        assert_eq!(
+            run(&mut filter, "Stream", "wss://relay.example"),
+            Action::Reject
+        );
+        assert_eq!(filter.gauges(), vec![("ratelimit_buckets", 2)]);
+    }
+
+    #[test]
+    fn test_throttled() {
+        let throttled = Throttled::default();
+        let mut filter: RateLimit = load_filter("posts_per_minute = 1");
+        filter.set_throttled(&throttled);
+
+        let mut input_message = mock_input();
+        input_message.source_type = "IP4".to_string();
+        input_message.source_info = "192.0.2.1".to_string();
+        let window = Duration::from_secs(60);
+
+        // notes from lookback use up tokens, but aren't throttled
+        filter.observe(&input_message);
+        filter.observe(&input_message);
+        assert!(!throttled.contains("192.0.2.1", window));
+
+        assert_eq!(filter.filter_note(&input_message).action, Action::Reject);
+        assert!(throttled.contains("192.0.2.1", window));
+        assert!(throttled.contains(&input_message.event.pubkey.to_hex(), window));
+
+        // each noteguard has its own throttled sources
+        assert!(!Throttled::default().contains("192.0.2.1", window));
     }
 }
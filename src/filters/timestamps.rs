@@ -0,0 +1,178 @@
+use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What `created_at` is compared against
+#[derive(Deserialize, Default, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Clock {
+    /// When strfry received the note
+    #[default]
+    ReceivedAt,
+
+    /// The current time
+    Wall,
+}
+
+impl Clock {
+    /// The current time in unix seconds according to this clock
+    pub fn now(self, input: &InputMessage) -> i64 {
+        match self {
+            Clock::ReceivedAt => i64::try_from(input.received_at).unwrap_or(i64::MAX),
+            Clock::Wall => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64),
+        }
+    }
+}
+
+/// How far `created_at` may be from the clock, in seconds. Unset bounds fall
+/// back to the next less specific setting.
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+pub struct Bounds {
+    pub max_future: Option<u64>,
+    pub max_past: Option<u64>,
+}
+
+fn default_exempt_source_types() -> Vec<String> {
+    vec!["Import".to_string(), "Sync".to_string()]
+}
+
+#[derive(Deserialize)]
+// flattened settings are invisible to the unknown setting check
+#[serde(deny_unknown_fields)]
+pub struct Timestamps {
+    #[serde(flatten)]
+    pub bounds: Bounds,
+
+    #[serde(default)]
+    pub clock: Clock,
+
+    /// Bounds for specific kinds, these take precedence over `source_types`
+    pub kinds: Option<HashMap<String, Bounds>>,
+
+    /// Bounds for specific strfry source types
+    pub source_types: Option<HashMap<String, Bounds>>,
+
+    /// Source types that are never checked, eg: historical imports
+    #[serde(default = "default_exempt_source_types")]
+    pub exempt_source_types: Vec<String>,
+}
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Timestamps {
+            bounds: Bounds::default(),
+            clock: Clock::default(),
+            kinds: None,
+            source_types: None,
+            exempt_source_types: default_exempt_source_types(),
+        }
+    }
+}
+
+impl Timestamps {
+    fn bounds(&self, input: &InputMessage) -> Bounds {
+        let kind = self
+            .kinds
+            .as_ref()
+            .and_then(|kinds| kinds.get(&input.event.kind.to_string()));
+        let source_type = self
+            .source_types
+            .as_ref()
+            .and_then(|source_types| source_types.get(&input.source_type));
+
+        let mut bounds = self.bounds;
+        for more_specific in [source_type, kind].into_iter().flatten() {
+            bounds.max_future = more_specific.max_future.or(bounds.max_future);
+            bounds.max_past = more_specific.max_past.or(bounds.max_past);
+        }
+        bounds
+    }
+}
+
+impl NoteFilter for Timestamps {
+    fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        if self.exempt_source_types.contains(&input.source_type) {
+            return OutputMessage::new(input.event.id.to_string(), Action::Accept, None);
+        }
+
+        let bounds = self.bounds(input);
+        // widened so extreme created_at values can't overflow
+        let offset = i128::from(input.event.created_at) - i128::from(self.clock.now(input));
+
+        let reject_message = match bounds {
+            Bounds {
+                max_future: Some(max_future),
+                ..
+            } if offset > i128::from(max_future) => "invalid: created_at is too far in the future",
+            Bounds {
+                max_past: Some(max_past),
+                ..
+            } if -offset > i128::from(max_past) => "invalid: created_at is too far in the past",
+            _ => return OutputMessage::new(input.event.id.to_string(), Action::Accept, None),
+        };
+
+        OutputMessage::new(
+            input.event.id.to_string(),
+            Action::Reject,
+            Some(reject_message.to_string()),
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        "timestamps"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input};
+    use crate::Kind;
+
+    #[test]
+    fn test_timestamps() {
+        let mut filter: Timestamps = load_filter(
+            r#"
+            max_future = 60
+            max_past = 3600
+
+            [kinds.0]
+            max_past = 100000
+        "#,
+        );
+
+        let at = |created_at: i64| {
+            let mut input_message = mock_input();
+            input_message.received_at = 100000;
+            input_message.event.created_at = created_at;
+            input_message
+        };
+
+        assert_eq!(filter.filter_note(&at(100030)).action, Action::Accept);
+
+        let output_message = filter.filter_note(&at(100061));
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("invalid: created_at is too far in the future")
+        );
+
+        assert_eq!(filter.filter_note(&at(0)).action, Action::Reject);
+
+        // imports are exempt by default
+        let mut input_message = at(0);
+        input_message.source_type = "Import".to_string();
+        assert_eq!(filter.filter_note(&input_message).action, Action::Accept);
+
+        // kind 0 has a longer max_past
+        let mut input_message = at(0);
+        input_message.event.kind = Kind(0);
+        assert_eq!(filter.filter_note(&input_message).action, Action::Accept);
+
+        assert_eq!(filter.filter_note(&at(i64::MIN)).action, Action::Reject);
+        assert_eq!(filter.filter_note(&at(i64::MAX)).action, Action::Reject);
+    }
+}
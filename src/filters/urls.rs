@@ -0,0 +1,368 @@
+use crate::{Action, InputMessage, List, Note, NoteFilter, OutputMessage, Pubkey};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use url::Url;
+
+fn url_regex() -> &'static Regex {
+    static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+    URL_REGEX.get_or_init(|| {
+        Regex::new(r#"(?i)\b(?:https?|wss?)://[^\s<>"'`]+"#).expect("valid url regex")
+    })
+}
+
+/// The urls in a note's content and in its `r`, `url` and `imeta` tags
+pub fn note_urls(note: &Note) -> Vec<&str> {
+    let mut urls: Vec<&str> = url_regex()
+        .find_iter(&note.content)
+        .map(|m| {
+            m.as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}'])
+        })
+        .collect();
+
+    for tag in note.tags.iter() {
+        match tag.name() {
+            Some("r") | Some("url") => urls.extend(tag.value()),
+            Some("imeta") => urls.extend(tag.iter().skip(1).filter_map(|entry| {
+                entry
+                    .strip_prefix("url ")
+                    .or_else(|| entry.strip_prefix("fallback "))
+            })),
+            _ => {}
+        }
+    }
+
+    urls.sort_unstable();
+    urls.dedup();
+    urls
+}
+
+/// Lowercases and punycode encodes a host, and strips any leading `www.`
+pub fn normalize_host(host: &str) -> Option<String> {
+    let url = Url::parse(&format!("http://{}/", host.trim_end_matches('.'))).ok()?;
+    let host = url.host_str()?;
+    Some(host.strip_prefix("www.").unwrap_or(host).to_string())
+}
+
+/// A set of hosts, normalized with [`normalize_host`]
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(try_from = "Vec<String>")]
+pub struct DomainSet(HashSet<String>);
+
+impl TryFrom<Vec<String>> for DomainSet {
+    type Error = String;
+
+    fn try_from(domains: Vec<String>) -> Result<Self, Self::Error> {
+        domains
+            .into_iter()
+            .map(|domain| {
+                normalize_host(&domain).ok_or_else(|| format!("invalid domain '{}'", domain))
+            })
+            .collect::<Result<HashSet<String>, String>>()
+            .map(DomainSet)
+    }
+}
+
+impl DomainSet {
+    pub fn contains(&self, host: &str) -> bool {
+        self.0.contains(host)
+    }
+
+    /// Whether the host or any domain it is a subdomain of is in the set
+    pub fn contains_suffix(&self, host: &str) -> bool {
+        self.contains(host)
+            || host
+                .match_indices('.')
+                .any(|(ind, _)| self.contains(&host[ind + 1..]))
+    }
+}
+
+fn default_known_after() -> u32 {
+    5
+}
+
+fn default_forget_after() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+/// How often pubkeys that haven't posted within `forget_after` are pruned
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Accepted notes from a pubkey, counted up to `known_after`
+struct Seen {
+    notes: u32,
+    last_note: Instant,
+}
+
+#[derive(Deserialize)]
+pub struct Urls {
+    /// Hosts that are blocked
+    #[serde(default)]
+    pub block_domains: List<DomainSet>,
+
+    /// Domains that are blocked along with all of their subdomains
+    #[serde(default)]
+    pub block_suffixes: List<DomainSet>,
+
+    /// Hosts that are never blocked or counted towards `max_links_unknown`
+    #[serde(default)]
+    pub allow_domains: List<DomainSet>,
+
+    /// Like `allow_domains`, but including subdomains
+    #[serde(default)]
+    pub allow_suffixes: List<DomainSet>,
+
+    pub message: Option<String>,
+
+    /// The most links a note from an unknown pubkey may have
+    pub max_links_unknown: Option<usize>,
+
+    /// Pubkeys that are always known
+    #[serde(default)]
+    pub known_pubkeys: List<HashSet<Pubkey>>,
+
+    /// How many accepted notes a pubkey needs before it is known
+    #[serde(default = "default_known_after")]
+    pub known_after: u32,
+
+    /// How long, in seconds, a pubkey's accepted notes are remembered
+    /// after its last one
+    #[serde(default = "default_forget_after")]
+    pub forget_after: u64,
+
+    /// Accepted notes seen from each pubkey
+    #[serde(skip)]
+    seen: HashMap<Pubkey, Seen>,
+
+    #[serde(skip)]
+    last_prune: Option<Instant>,
+}
+
+impl Default for Urls {
+    fn default() -> Self {
+        Urls {
+            block_domains: List::default(),
+            block_suffixes: List::default(),
+            allow_domains: List::default(),
+            allow_suffixes: List::default(),
+            message: None,
+            max_links_unknown: None,
+            known_pubkeys: List::default(),
+            known_after: default_known_after(),
+            forget_after: default_forget_after(),
+            seen: HashMap::new(),
+            last_prune: None,
+        }
+    }
+}
+
+impl Urls {
+    fn reload(&mut self) {
+        self.block_domains.reload();
+        self.block_suffixes.reload();
+        self.allow_domains.reload();
+        self.allow_suffixes.reload();
+        self.known_pubkeys.reload();
+    }
+
+    fn is_blocked(&self, host: &str) -> bool {
+        self.block_domains.contains(host) || self.block_suffixes.contains_suffix(host)
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        self.allow_domains.contains(host) || self.allow_suffixes.contains_suffix(host)
+    }
+
+    fn is_known(&self, pubkey: &Pubkey) -> bool {
+        self.known_pubkeys.contains(pubkey)
+            || self
+                .seen
+                .get(pubkey)
+                .is_some_and(|seen| seen.notes >= self.known_after)
+    }
+
+    fn count(&mut self, input: &InputMessage) {
+        if self.max_links_unknown.is_none() {
+            return;
+        }
+
+        let now = Instant::now();
+        let forget_after = Duration::from_secs(self.forget_after);
+        if self.last_prune.is_none_or(|at| now - at >= PRUNE_INTERVAL) {
+            self.seen
+                .retain(|_, seen| now - seen.last_note < forget_after);
+            self.last_prune = Some(now);
+        }
+
+        let seen = self.seen.entry(input.event.pubkey).or_insert(Seen {
+            notes: 0,
+            last_note: now,
+        });
+        seen.notes = (seen.notes + 1).min(self.known_after);
+        seen.last_note = now;
+    }
+
+    fn check(&self, note: &Note) -> Result<(), String> {
+        let mut links = 0;
+
+        for url in note_urls(note) {
+            let Some(host) = Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().and_then(normalize_host))
+            else {
+                continue;
+            };
+
+            if self.is_allowed(&host) {
+                continue;
+            }
+
+            if self.is_blocked(&host) {
+                return Err(self
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| format!("blocked: links to {} are not allowed", host)));
+            }
+
+            links += 1;
+        }
+
+        match self.max_links_unknown {
+            Some(max) if links > max && !self.is_known(&note.pubkey) => Err(format!(
+                "blocked: too many links for a new account (max {})",
+                max
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl NoteFilter for Urls {
+    fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        self.reload();
+
+        let id = input.event.id.to_string();
+
+        match self.check(&input.event) {
+            Ok(()) => OutputMessage::new(id, Action::Accept, None),
+            Err(msg) => OutputMessage::new(id, Action::Reject, Some(msg)),
+        }
+    }
+
+    fn observe(&mut self, input: &InputMessage) {
+        self.count(input);
+    }
+
+    fn accepted(&mut self, input: &InputMessage) {
+        self.count(input);
+    }
+
+    fn gauges(&self) -> Vec<(&'static str, u64)> {
+        vec![("urls_seen_pubkeys", self.seen.len() as u64)]
+    }
+
+    fn name(&self) -> &'static str {
+        "urls"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_filter, mock_input, tags, OTHER_PUBKEY};
+
+    #[test]
+    fn test_urls() {
+        let mut filter: Urls = load_filter(
+            r#"
+            block_domains = ["spam.example"]
+            block_suffixes = ["xyz", "bücher.example"]
+            allow_suffixes = ["nostr.build"]
+            max_links_unknown = 1
+            known_after = 1
+        "#,
+        );
+
+        let run = |filter: &mut Urls, content: &str, note_tags| {
+            let mut input_message = mock_input();
+            input_message.event.content = content.to_string();
+            input_message.event.tags = tags(note_tags);
+            filter.filter_note(&input_message)
+        };
+        let no_tags = serde_json::json!([]);
+
+        let output_message = run(
+            &mut filter,
+            "check this out: https://WWW.Spam.Example/deal.",
+            no_tags.clone(),
+        );
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: links to spam.example are not allowed")
+        );
+
+        let output_message = run(
+            &mut filter,
+            "",
+            serde_json::json!([["imeta", "url https://cdn.shop.xyz/a.png", "m image/png"]]),
+        );
+        assert_eq!(output_message.action, Action::Reject);
+
+        // unicode hosts are compared in their punycode form
+        let output_message = run(
+            &mut filter,
+            "",
+            serde_json::json!([["r", "https://shop.xn--bcher-kva.example"]]),
+        );
+        assert_eq!(output_message.action, Action::Reject);
+
+        // allowed links don't count towards the cap for unknown pubkeys
+        let output_message = run(
+            &mut filter,
+            "https://i.nostr.build/a.png https://damus.io",
+            no_tags.clone(),
+        );
+        assert_eq!(output_message.action, Action::Accept);
+
+        let output_message = run(
+            &mut filter,
+            "https://damus.io https://github.com",
+            no_tags.clone(),
+        );
+        assert_eq!(output_message.action, Action::Reject);
+
+        // notes only count once the whole pipeline accepted them
+        filter.accepted(&mock_input());
+        let output_message = run(
+            &mut filter,
+            "https://damus.io https://github.com",
+            no_tags.clone(),
+        );
+        assert_eq!(output_message.action, Action::Accept);
+
+        let mut input_message = mock_input();
+        input_message.event.pubkey = OTHER_PUBKEY.parse().expect("invalid pubkey");
+        input_message.event.content = "https://damus.io https://github.com".to_string();
+        let output_message = filter.filter_note(&input_message);
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: too many links for a new account (max 1)")
+        );
+
+        // counts stop at known_after
+        for _ in 0..10 {
+            filter.accepted(&mock_input());
+        }
+        assert_eq!(filter.seen.values().next().map(|seen| seen.notes), Some(1));
+
+        filter.forget_after = 0;
+        filter.last_prune = None;
+        filter.accepted(&input_message);
+        assert_eq!(filter.gauges(), vec![("urls_seen_pubkeys", 1)]);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{Action, InputMessage, Note, NoteFilter, OutputMessage};
+use secp256k1::{schnorr, Message, XOnlyPublicKey, SECP256K1};
+use serde::Deserialize;
+use std::fmt;
+
+/// Why a note failed verification
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum VerifyError {
+    /// The id is not the hash of the note
+    IdMismatch,
+
+    /// The pubkey is not a valid x-only public key
+    InvalidPubkey,
+
+    /// The signature is not a valid Schnorr signature encoding
+    InvalidSignature,
+
+    /// The signature does not match the pubkey and id
+    BadSignature,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::IdMismatch => write!(f, "invalid: event id does not match"),
+            VerifyError::InvalidPubkey => write!(f, "invalid: bad pubkey"),
+            VerifyError::InvalidSignature => write!(f, "invalid: bad signature encoding"),
+            VerifyError::BadSignature => write!(f, "invalid: bad signature"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Check a note's id and BIP-340 signature
+pub fn verify_note(note: &Note) -> Result<(), VerifyError> {
+    let id = note.compute_id();
+    if id != note.id {
+        return Err(VerifyError::IdMismatch);
+    }
+
+    let pubkey = XOnlyPublicKey::from_slice(note.pubkey.as_bytes())
+        .map_err(|_| VerifyError::InvalidPubkey)?;
+
+    let sig = schnorr::Signature::from_slice(note.sig.as_bytes())
+        .map_err(|_| VerifyError::InvalidSignature)?;
+
+    SECP256K1
+        .verify_schnorr(&sig, &Message::from_digest(*id.as_bytes()), &pubkey)
+        .map_err(|_| VerifyError::BadSignature)
+}
+
+#[derive(Deserialize, Default)]
+pub struct Verify {}
+
+impl NoteFilter for Verify {
+    fn filter_note(&mut self, input: &InputMessage) -> OutputMessage {
+        match verify_note(&input.event) {
+            Ok(()) => OutputMessage::new(input.event.id.to_string(), Action::Accept, None),
+            Err(err) => OutputMessage::new(
+                input.event.id.to_string(),
+                Action::Reject,
+                Some(err.to_string()),
+            ),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "verify"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_note() -> Note {
+        serde_json::from_str(
+            r#"{"id": "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76","pubkey": "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93","created_at": 1720408658,"kind": 1,"tags": [],"content": "hi","sig": "7b76471744ded2b720ca832cdc89e670f6093ce38aeef55a5c6a4e077883d7d80dda1e9051032fb1faa1c3c212c517e93ee42b3ceac8e8e9b04bad46a361de90"}"#,
+        )
+        .expect("Failed to parse note")
+    }
+
+    #[test]
+    fn test_verify_note() {
+        let note = signed_note();
+        assert_eq!(verify_note(&note), Ok(()));
+
+        let mut tampered = note.clone();
+        tampered.content = "bye".to_string();
+        assert_eq!(verify_note(&tampered), Err(VerifyError::IdMismatch));
+
+        let mut forged = note;
+        forged.sig = forged
+            .sig
+            .to_string()
+            .replace("7b76", "7b77")
+            .parse()
+            .expect("invalid signature");
+        assert_eq!(verify_note(&forged), Err(VerifyError::BadSignature));
+    }
+}
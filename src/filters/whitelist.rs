@@ -1,31 +1,60 @@
-use crate::{Action, InputMessage, NoteFilter, OutputMessage};
+use crate::{Action, InputMessage, IpSet, List, NoteFilter, OutputMessage, Pubkey};
 use serde::Deserialize;
+use std::collections::HashSet;
 
 #[derive(Deserialize, Default)]
 pub struct Whitelist {
-    pub pubkeys: Option<Vec<String>>,
-    pub ips: Option<Vec<String>>,
+    pub pubkeys: Option<List<HashSet<Pubkey>>>,
+    pub ips: Option<List<IpSet>>,
+
+    /// Accept matching notes without running the rest of the pipeline, and
+    /// let everything else through to the next filter instead of rejecting it.
+    #[serde(default)]
+    pub allowlist: bool,
 }
 
-impl NoteFilter for Whitelist {
-    fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage {
+impl Whitelist {
+    fn matches(&self, msg: &InputMessage) -> bool {
         if let Some(pubkeys) = &self.pubkeys {
             if pubkeys.contains(&msg.event.pubkey) {
-                return OutputMessage::new(msg.event.id.clone(), Action::Accept, None);
+                return true;
             }
         }
 
-        if let Some(ips) = &self.ips {
-            if ips.contains(&msg.source_info) {
-                return OutputMessage::new(msg.event.id.clone(), Action::Accept, None);
+        if let (Some(ips), Some(addr)) = (&self.ips, msg.source_ip()) {
+            if ips.contains(addr) {
+                return true;
             }
         }
 
-        OutputMessage::new(
-            msg.event.id.clone(),
-            Action::Reject,
-            Some("blocked: pubkey/ip not on the whitelist".to_string()),
-        )
+        false
+    }
+
+    fn reload(&mut self) {
+        if let Some(pubkeys) = &mut self.pubkeys {
+            pubkeys.reload();
+        }
+        if let Some(ips) = &mut self.ips {
+            ips.reload();
+        }
+    }
+}
+
+impl NoteFilter for Whitelist {
+    fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage {
+        self.reload();
+
+        match (self.matches(msg), self.allowlist) {
+            (true, true) => OutputMessage::new(msg.event.id.to_string(), Action::AcceptFinal, None),
+            (true, false) | (false, true) => {
+                OutputMessage::new(msg.event.id.to_string(), Action::Accept, None)
+            }
+            (false, false) => OutputMessage::new(
+                msg.event.id.to_string(),
+                Action::Reject,
+                Some("blocked: pubkey/ip not on the whitelist".to_string()),
+            ),
+        }
     }
 
     fn name(&self) -> &'static str {
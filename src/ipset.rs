@@ -0,0 +1,203 @@
+use serde::Deserialize;
+use std::net::IpAddr;
+
+/// A node in a binary prefix trie, children are indices into [`Trie::nodes`]
+#[derive(Default, Clone, Debug)]
+struct Node {
+    children: [Option<u32>; 2],
+    terminal: bool,
+}
+
+/// A binary trie of address prefixes, `width` bits wide
+#[derive(Clone, Debug)]
+struct Trie {
+    nodes: Vec<Node>,
+    width: u8,
+}
+
+impl Trie {
+    fn new(width: u8) -> Self {
+        Trie {
+            nodes: vec![Node::default()],
+            width,
+        }
+    }
+
+    fn bit(&self, addr: u128, ind: u8) -> usize {
+        ((addr >> (self.width - 1 - ind)) & 1) as usize
+    }
+
+    fn insert(&mut self, addr: u128, prefix_len: u8) {
+        let mut node = 0;
+        for ind in 0..prefix_len {
+            if self.nodes[node].terminal {
+                // a shorter prefix already covers this one
+                return;
+            }
+
+            let bit = self.bit(addr, ind);
+            node = match self.nodes[node].children[bit] {
+                Some(child) => child as usize,
+                None => {
+                    self.nodes.push(Node::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit] = Some(child as u32);
+                    child
+                }
+            };
+        }
+        self.nodes[node].terminal = true;
+    }
+
+    fn contains(&self, addr: u128) -> bool {
+        let mut node = 0;
+        for ind in 0..self.width {
+            if self.nodes[node].terminal {
+                return true;
+            }
+
+            match self.nodes[node].children[self.bit(addr, ind)] {
+                Some(child) => node = child as usize,
+                None => return false,
+            }
+        }
+        self.nodes[node].terminal
+    }
+}
+
+/// A set of IP addresses and CIDR ranges, eg: `"10.0.0.1"`, `"192.168.0.0/16"`
+/// or `"2001:db8::/48"`. IPv4-mapped IPv6 addresses match their IPv4 entries.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "Vec<String>")]
+pub struct IpSet {
+    v4: Trie,
+    v6: Trie,
+    len: usize,
+}
+
+impl Default for IpSet {
+    fn default() -> Self {
+        IpSet {
+            v4: Trie::new(32),
+            v6: Trie::new(128),
+            len: 0,
+        }
+    }
+}
+
+impl IpSet {
+    /// Adds an address or CIDR range
+    pub fn insert(&mut self, entry: &str) -> Result<(), String> {
+        let invalid = || format!("invalid ip address or range '{}'", entry);
+
+        let (addr, prefix_len) = match entry.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (entry, None),
+        };
+        let mut addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+
+        let width = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let mut prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.trim().parse::<u8>().map_err(|_| invalid())?,
+            None => width,
+        };
+        if prefix_len > width {
+            return Err(invalid());
+        }
+
+        // `contains` looks up IPv4-mapped addresses as IPv4, so mapped
+        // entries are stored that way too
+        if let IpAddr::V6(v6) = addr {
+            if let Some(v4) = v6.to_ipv4_mapped().filter(|_| prefix_len >= 96) {
+                addr = IpAddr::V4(v4);
+                prefix_len -= 96;
+            }
+        }
+
+        let trie = match addr {
+            IpAddr::V4(_) => &mut self.v4,
+            IpAddr::V6(_) => &mut self.v6,
+        };
+        trie.insert(addr_bits(addr), prefix_len);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr.to_canonical() {
+            addr @ IpAddr::V4(_) => self.v4.contains(addr_bits(addr)),
+            addr @ IpAddr::V6(_) => self.v6.contains(addr_bits(addr)),
+        }
+    }
+
+    /// The number of entries that were added
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+fn addr_bits(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(addr) as u128,
+        IpAddr::V6(addr) => u128::from(addr),
+    }
+}
+
+impl TryFrom<Vec<String>> for IpSet {
+    type Error = String;
+
+    fn try_from(entries: Vec<String>) -> Result<Self, Self::Error> {
+        let mut set = IpSet::default();
+        for entry in &entries {
+            set.insert(entry)?;
+        }
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_set() {
+        let set = IpSet::try_from(vec![
+            "127.0.0.1".to_string(),
+            "10.1.0.0/16".to_string(),
+            "2001:db8:42::/48".to_string(),
+        ])
+        .expect("Failed to parse ip set");
+
+        let contains = |addr: &str| set.contains(addr.parse().expect("invalid ip"));
+        assert!(contains("127.0.0.1"));
+        assert!(!contains("127.0.0.2"));
+        assert!(contains("10.1.255.7"));
+        assert!(!contains("10.2.0.1"));
+        assert!(contains("::ffff:10.1.2.3"));
+        assert!(contains("2001:db8:42:1::1"));
+        assert!(!contains("2001:db8:43::1"));
+
+        // IPv4-mapped entries match IPv4 addresses, mapped or not
+        let mapped = IpSet::try_from(vec![
+            "::ffff:192.0.2.0/120".to_string(),
+            "::ffff:10.0.0.1".to_string(),
+        ])
+        .expect("Failed to parse ip set");
+        let contains = |addr: &str| mapped.contains(addr.parse().expect("invalid ip"));
+        assert!(contains("192.0.2.7"));
+        assert!(contains("::ffff:192.0.2.7"));
+        assert!(!contains("192.0.3.7"));
+        assert!(contains("10.0.0.1"));
+        assert!(!contains("10.0.0.2"));
+
+        assert!(IpSet::try_from(vec!["10.0.0.0/33".to_string()]).is_err());
+        assert!(IpSet::try_from(vec!["localhost".to_string()]).is_err());
+    }
+}
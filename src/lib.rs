@@ -1,6 +1,26 @@
+mod audit;
+mod config;
 pub mod filters;
+mod ipset;
+mod list;
 mod messages;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod note;
 mod note_filter;
+mod noteguard;
+#[cfg(test)]
+mod test_utils;
+mod watch;
 
-pub use messages::{Action, InputMessage, OutputMessage};
-pub use note_filter::{Note, NoteFilter};
+pub use audit::{AuditConfig, AuditLog};
+pub use config::{Config, ConfigError, FilterMode, ListConfig, ListType, MetricsConfig, Route};
+pub use ipset::IpSet;
+pub use list::List;
+pub use messages::{Action, InputMessage, OutputMessage, Source};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use note::{Address, Kind, KindClass, Note, NoteId, Pubkey, Signature, Tag, Tags};
+pub use note_filter::NoteFilter;
+pub use noteguard::{Decision, FilterTiming, Noteguard, NoteguardBuilder};
+pub use watch::FileWatcher;
@@ -0,0 +1,246 @@
+use crate::FileWatcher;
+use log::{error, info};
+use serde::de::{DeserializeOwned, Error};
+use serde::{Deserialize, Deserializer};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often list files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A list setting, written inline like `["a", "b"]` or loaded from a file
+/// with `{ file = "list.txt" }`. List files have one entry per line, and
+/// blank lines and lines starting with `#` are ignored.
+///
+/// The entries are stored in `S`, eg: a `HashSet` or an [`IpSet`](crate::IpSet),
+/// which is deserialized from the inline value or from the file's lines as
+/// an array of strings.
+pub struct List<S> {
+    entries: S,
+    watcher: Option<FileWatcher>,
+}
+
+impl<S: DeserializeOwned> List<S> {
+    pub fn inline(entries: S) -> Self {
+        List {
+            entries,
+            watcher: None,
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let entries = read_list(path)?;
+
+        Ok(List {
+            entries,
+            watcher: Some(FileWatcher::new(path, RELOAD_INTERVAL)),
+        })
+    }
+
+    /// The file this list was loaded from, if any
+    pub fn path(&self) -> Option<&Path> {
+        self.watcher.as_ref().map(|watcher| watcher.path())
+    }
+
+    /// Reloads the list if its file changed, returning true if it was
+    /// reloaded. The new entries replace the old ones only once the whole
+    /// file has been read, and if it can't be read the old ones are kept.
+    pub fn reload(&mut self) -> bool {
+        let Some(watcher) = &mut self.watcher else {
+            return false;
+        };
+
+        if !watcher.changed() {
+            return false;
+        }
+
+        match read_list(watcher.path()) {
+            Ok(entries) => {
+                info!("reloaded {}", watcher.path().display());
+                self.entries = entries;
+                true
+            }
+            Err(err) => {
+                error!("{}, keeping the previous list", err);
+                false
+            }
+        }
+    }
+}
+
+impl<S: Default> Default for List<S> {
+    fn default() -> Self {
+        List {
+            entries: S::default(),
+            watcher: None,
+        }
+    }
+}
+
+impl<S> Deref for List<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.entries
+    }
+}
+
+/// The non-empty, non-comment lines of a list file
+pub fn read_lines(path: &Path) -> Result<Vec<(usize, String)>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+    Ok(contents
+        .lines()
+        .enumerate()
+        .map(|(ind, line)| (ind + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| (line_number, line.to_string()))
+        .collect())
+}
+
+fn read_list<S: DeserializeOwned>(path: &Path) -> Result<S, String> {
+    let lines = read_lines(path)?;
+    let value = toml::Value::Array(
+        lines
+            .iter()
+            .map(|(_, line)| toml::Value::String(line.clone()))
+            .collect(),
+    );
+
+    serde_path_to_error::deserialize(value).map_err(|err| {
+        let line_number = match err.path().iter().next() {
+            Some(serde_path_to_error::Segment::Seq { index }) => lines.get(*index).map(|l| l.0),
+            _ => None,
+        };
+
+        match line_number {
+            Some(line_number) => format!("{}:{}: {}", path.display(), line_number, err.inner()),
+            None => format!("{}: {}", path.display(), err.inner()),
+        }
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListFile {
+    file: PathBuf,
+}
+
+impl<'de, S: DeserializeOwned> Deserialize<'de> for List<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = toml::Value::deserialize(deserializer)?;
+
+        if value.is_table() {
+            let list_file = ListFile::deserialize(value).map_err(D::Error::custom)?;
+            return List::from_file(list_file.file).map_err(D::Error::custom);
+        }
+
+        serde_path_to_error::deserialize(value)
+            .map(List::inline)
+            .map_err(|err| match err.path().to_string().as_str() {
+                "." => D::Error::custom(err.into_inner()),
+                path => D::Error::custom(format!("{}: {}", path, err.into_inner())),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::{Blacklist, Content, Kinds};
+    use crate::test_utils::{load_filter, mock_input, OTHER_PUBKEY};
+    use crate::{Action, Kind, NoteFilter, Pubkey};
+    use std::collections::HashSet;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_list_file() {
+        let path = std::env::temp_dir().join(format!("noteguard-list-{}", std::process::id()));
+        let pubkey = "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93";
+        std::fs::write(&path, format!("# staff\n\n{}\n", pubkey)).expect("Failed to write list");
+
+        let value: toml::Value =
+            toml::from_str(&format!("file = '{}'", path.display())).expect("Failed to parse toml");
+        let list: List<HashSet<Pubkey>> = List::deserialize(value).expect("Failed to load list");
+        assert_eq!(list.len(), 1);
+        assert!(list.contains(&pubkey.parse().unwrap()));
+        assert_eq!(list.path(), Some(path.as_path()));
+
+        let write = |contents: String, mtime: u64| {
+            std::fs::write(&path, contents).expect("Failed to write list");
+            std::fs::File::open(&path)
+                .and_then(|file| file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime)))
+                .expect("Failed to set mtime");
+        };
+        let mut list = List {
+            watcher: Some(FileWatcher::new(&path, Duration::ZERO)),
+            ..list
+        };
+
+        // a bad entry keeps the previous list
+        write(format!("{}\nnot-a-pubkey\n", pubkey), 1000);
+        assert!(!list.reload());
+        assert_eq!(list.len(), 1);
+
+        let err = read_list::<HashSet<Pubkey>>(&path).expect_err("expected an error");
+        assert!(err.contains(":2: "), "{}", err);
+
+        let other = "5f54041530509de28550475bfe73db709609a4ee1e59281527ba81692923418f";
+        write(format!("{}\n{}\n", pubkey, other), 2000);
+        assert!(list.reload());
+        assert_eq!(list.len(), 2);
+
+        std::fs::remove_file(&path).expect("Failed to remove list");
+    }
+
+    #[test]
+    fn test_list_files() {
+        let dir = std::env::temp_dir().join(format!("noteguard-lists-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create dir");
+        std::fs::write(
+            dir.join("banned.txt"),
+            format!("# banned\n{}\n", OTHER_PUBKEY),
+        )
+        .expect("Failed to write list");
+        std::fs::write(dir.join("words.txt"), "casino\nairdrop\n").expect("Failed to write list");
+        std::fs::write(dir.join("kinds.txt"), "4\n20000-29999\n").expect("Failed to write list");
+
+        let mut blacklist: Blacklist = load_filter(&format!(
+            "pubkeys = {{ file = '{}/banned.txt' }}",
+            dir.display()
+        ));
+        let mut input_message = mock_input();
+        input_message.event.pubkey = OTHER_PUBKEY.parse().expect("invalid pubkey");
+        assert_eq!(blacklist.filter_note(&input_message).action, Action::Reject);
+
+        let mut kinds: Kinds = load_filter(&format!(
+            "kinds = {{ file = '{}/kinds.txt' }}",
+            dir.display()
+        ));
+        let mut input_message = mock_input();
+        input_message.event.kind = Kind(20001);
+        assert_eq!(kinds.filter_note(&input_message).action, Action::Reject);
+
+        let mut content: Content = load_filter(&format!(
+            "filters = {{ file = '{}/words.txt' }}",
+            dir.display()
+        ));
+        let mut input_message = mock_input();
+        input_message.event.content = "online casino".to_string();
+        assert_eq!(
+            content.filter_note(&input_message).action,
+            Action::ShadowReject
+        );
+
+        let missing = toml::from_str::<Blacklist>(&format!(
+            "ips = {{ file = '{}/missing.txt' }}",
+            dir.display()
+        ));
+        assert!(missing.is_err(), "missing list file should fail");
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove dir");
+    }
+}
@@ -1,395 +1,114 @@
-use noteguard::filters::{Blacklist, Content, Kinds, ProtectedEvents, RateLimit, Whitelist};
+use log::{error, info};
+use noteguard::{Config, FileWatcher, Noteguard, OutputMessage};
+use std::io::{self, BufRead};
+use std::process::ExitCode;
+use std::time::Duration;
 
-#[cfg(feature = "forwarder")]
-use noteguard::filters::Forwarder;
+const DEFAULT_CONFIG: &str = "noteguard.toml";
 
-use log::info;
-use noteguard::{Action, InputMessage, NoteFilter, OutputMessage};
-use serde::de::DeserializeOwned;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::io::{self, Read};
+/// How often to check the config file for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
 
-#[derive(Deserialize)]
-struct Config {
-    pipeline: Vec<String>,
-    filters: HashMap<String, toml::Value>,
+#[cfg(feature = "forwarder")]
+#[tokio::main]
+async fn main() -> ExitCode {
+    cli()
 }
 
-type ConstructFilter = Box<fn(toml::Value) -> Result<Box<dyn NoteFilter>, toml::de::Error>>;
-
-#[derive(Default)]
-struct Noteguard {
-    registered_filters: HashMap<String, ConstructFilter>,
-    loaded_filters: Vec<Box<dyn NoteFilter>>,
+#[cfg(not(feature = "forwarder"))]
+fn main() -> ExitCode {
+    cli()
 }
 
-impl Noteguard {
-    pub fn new() -> Self {
-        let mut noteguard = Noteguard::default();
-        noteguard.register_builtin_filters();
-        noteguard
-    }
-
-    pub fn register_filter<F: NoteFilter + 'static + Default + DeserializeOwned>(&mut self) {
-        self.registered_filters.insert(
-            F::name(&F::default()).to_string(),
-            Box::new(|filter_config| {
-                filter_config
-                    .try_into()
-                    .map(|filter: F| Box::new(filter) as Box<dyn NoteFilter>)
-            }),
-        );
-    }
-
-    /// All builtin filters are registered here, and are made available with
-    /// every new instance of [`Noteguard`]
-    fn register_builtin_filters(&mut self) {
-        self.register_filter::<RateLimit>();
-        self.register_filter::<Whitelist>();
-        self.register_filter::<Blacklist>();
-        self.register_filter::<ProtectedEvents>();
-        self.register_filter::<Kinds>();
-        self.register_filter::<Content>();
-
-        #[cfg(feature = "forwarder")]
-        self.register_filter::<Forwarder>();
-    }
-
-    /// Run the loaded filters. You must call `load_config` before calling this, otherwise
-    /// not filters will be run.
-    fn run(&mut self, input: InputMessage) -> OutputMessage {
-        let mut mout: Option<OutputMessage> = None;
+fn cli() -> ExitCode {
+    env_logger::init();
 
-        let id = input.event.id.clone();
-        for filter in &mut self.loaded_filters {
-            let out = filter.filter_note(&input);
-            match out.action {
-                Action::Accept => {
-                    mout = Some(out);
-                    continue;
-                }
-                Action::Reject => {
-                    return out;
-                }
-                Action::ShadowReject => {
-                    return out;
-                }
-            }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        None => noteguard(DEFAULT_CONFIG),
+        Some("check") if args.len() <= 2 => {
+            check(args.get(1).map(|s| s.as_str()).unwrap_or(DEFAULT_CONFIG))
+        }
+        Some(_) => {
+            eprintln!("usage: noteguard [check [config]]");
+            ExitCode::FAILURE
         }
-
-        mout.unwrap_or_else(|| OutputMessage::new(id, Action::Accept, None))
     }
+}
 
-    /// Initializes a noteguard config. If it finds any filter configurations
-    /// matching the registered filters, it loads those into our filter pipeline.
-    fn load_config(&mut self, config: &Config) -> Result<(), toml::de::Error> {
-        self.loaded_filters.clear();
-
-        for name in &config.pipeline {
-            let config_value = config
-                .filters
-                .get(name)
-                .unwrap_or_else(|| panic!("could not find filter configuration for {}", name));
-
-            if let Some(constructor) = self.registered_filters.get(name.as_str()) {
-                let filter = constructor(config_value.clone())?;
-                self.loaded_filters.push(filter);
-            } else {
-                panic!("Found config settings with no matching filter: {}", name);
-            }
+/// Validate a config file, printing every problem found
+fn check(config_path: &str) -> ExitCode {
+    let config = match Config::from_path(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {}", config_path, err);
+            return ExitCode::FAILURE;
         }
+    };
 
-        Ok(())
+    let errors = Noteguard::new().check_config(&config);
+    if errors.is_empty() {
+        println!(
+            "{}: ok ({} filters in pipeline)",
+            config_path,
+            config.pipeline.len()
+        );
+        return ExitCode::SUCCESS;
     }
-}
-
-#[cfg(feature = "forwarder")]
-#[tokio::main]
-async fn main() {
-    noteguard();
-}
 
-#[cfg(not(feature = "forwarder"))]
-fn main() {
-    noteguard();
+    for err in &errors {
+        eprintln!("{}: {}", config_path, err);
+    }
+    ExitCode::FAILURE
 }
 
 fn serialize_output_message(msg: &OutputMessage) -> String {
     serde_json::to_string(msg).expect("OutputMessage should always serialize correctly")
 }
 
-fn noteguard() {
-    env_logger::init();
+fn noteguard(config_path: &str) -> ExitCode {
     info!("running noteguard");
 
-    let config_path = "noteguard.toml";
     let mut noteguard = Noteguard::new();
 
-    let config: Config = {
-        let mut file = std::fs::File::open(config_path).expect("Failed to open config file");
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .expect("Failed to read config file");
-        toml::from_str(&contents).expect("Failed to parse config file")
-    };
-
-    noteguard
-        .load_config(&config)
-        .expect("Expected filter config to be loaded ok");
+    if let Err(err) = noteguard.load_config_path(config_path) {
+        error!("{}: {}", config_path, err);
+        return ExitCode::FAILURE;
+    }
 
-    let stdin = io::stdin();
+    let mut config_watcher = FileWatcher::new(config_path, RELOAD_INTERVAL);
+    let mut stdin = io::stdin().lock();
+    let mut buf = vec![];
 
-    for line in stdin.lines() {
-        let line = match line {
-            Ok(line) => line,
+    loop {
+        buf.clear();
+        match stdin.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
             Err(e) => {
-                eprintln!("Failed to get line: {}", e);
-                continue;
+                error!("Failed to read input: {}", e);
+                break;
             }
-        };
+        }
 
-        let input_message: InputMessage = match serde_json::from_str(&line) {
-            Ok(msg) => msg,
-            Err(e) => {
-                eprintln!("Failed to parse input: {}", e);
-                continue;
+        if config_watcher.changed() {
+            match noteguard.reload_config_path(config_path) {
+                Ok(()) => info!("reloaded {}", config_path),
+                Err(err) => error!("{}: {}, keeping the previous config", config_path, err),
             }
-        };
-
-        if input_message.message_type != "new" {
-            let out = OutputMessage::new(
-                input_message.event.id.clone(),
-                Action::Reject,
-                Some("invalid strfry write policy input".to_string()),
-            );
-            println!("{}", serialize_output_message(&out));
-            continue;
         }
 
-        let out = noteguard.run(input_message);
-        let json = serialize_output_message(&out);
-
-        println!("{}", json);
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use noteguard::filters::{Kinds, ProtectedEvents, RateLimit, Whitelist};
-    use noteguard::{Action, Note};
-    use serde_json::json;
-
-    // Helper function to create a mock InputMessage
-    fn create_mock_input_message(event_id: &str, message_type: &str) -> InputMessage {
-        InputMessage {
-            message_type: message_type.to_string(),
-            event: Note {
-                id: event_id.to_string(),
-                pubkey: "mock_pubkey".to_string(),
-                created_at: 0,
-                kind: 1,
-                tags: vec![vec!["-".to_string()]],
-                content: "mock_content".to_string(),
-                sig: "mock_signature".to_string(),
-            },
-            received_at: 0,
-            source_type: "mock_source".to_string(),
-            source_info: "mock_source_info".to_string(),
+        // invalid utf-8 still gets a (rejection) response
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.trim().is_empty() {
+            continue;
         }
-    }
 
-    // Helper function to create a mock OutputMessage
-    fn create_mock_output_message(
-        event_id: &str,
-        action: Action,
-        msg: Option<&str>,
-    ) -> OutputMessage {
-        OutputMessage {
-            id: event_id.to_string(),
-            action,
-            msg: msg.map(|s| s.to_string()),
+        if let Some(out) = noteguard.handle_line(line) {
+            println!("{}", serialize_output_message(&out));
         }
     }
 
-    #[test]
-    fn test_register_builtin_filters() {
-        let noteguard = Noteguard::new();
-        assert!(noteguard.registered_filters.contains_key("ratelimit"));
-        assert!(noteguard.registered_filters.contains_key("whitelist"));
-        assert!(noteguard.registered_filters.contains_key("blacklist"));
-        assert!(noteguard
-            .registered_filters
-            .contains_key("protected_events"));
-        assert!(noteguard.registered_filters.contains_key("kinds"));
-    }
-
-    #[test]
-    fn test_load_config() {
-        let mut noteguard = Noteguard::new();
-
-        // Create a mock config with one filter (RateLimit)
-        let config: Config = toml::from_str(
-            r#"
-            pipeline = ["ratelimit"]
-
-            [filters.ratelimit]
-            posts_per_minute = 3
-        "#,
-        )
-        .expect("Failed to parse config");
-
-        assert!(noteguard.load_config(&config).is_ok());
-        assert_eq!(noteguard.loaded_filters.len(), 1);
-    }
-
-    #[test]
-    fn test_run_filters_accept() {
-        let mut noteguard = Noteguard::new();
-
-        // Create a mock config with one filter (RateLimit)
-        let config: Config = toml::from_str(
-            r#"
-            pipeline = ["ratelimit"]
-
-            [filters.ratelimit]
-            posts_per_minute = 3
-        "#,
-        )
-        .expect("Failed to parse config");
-
-        noteguard
-            .load_config(&config)
-            .expect("Failed to load config");
-
-        let input_message = create_mock_input_message("test_event_1", "new");
-        let output_message = noteguard.run(input_message);
-
-        assert_eq!(output_message.action, Action::Accept);
-    }
-
-    #[test]
-    fn test_run_filters_shadow_reject() {
-        let mut noteguard = Noteguard::new();
-
-        // Create a mock config with one filter (ProtectedEvents) which will shadow reject the input
-        let config: Config = toml::from_str(
-            r#"
-            pipeline = ["protected_events"]
-
-            [filters.protected_events]
-        "#,
-        )
-        .expect("Failed to parse config");
-
-        noteguard
-            .load_config(&config)
-            .expect("Failed to load config");
-
-        let input_message = create_mock_input_message("test_event_3", "new");
-        let output_message = noteguard.run(input_message);
-
-        assert_eq!(output_message.action, Action::Reject);
-    }
-
-    #[test]
-    fn test_whitelist_reject() {
-        let mut noteguard = Noteguard::new();
-
-        // Create a mock config with one filter (Whitelist) which will reject the input
-        let config: Config = toml::from_str(
-            r#"
-            pipeline = ["whitelist"]
-            [filters.whitelist]
-            pubkeys = ["something"]
-        "#,
-        )
-        .expect("Failed to parse config");
-
-        noteguard
-            .load_config(&config)
-            .expect("Failed to load config");
-
-        let input_message = create_mock_input_message("test_event_2", "new");
-        let output_message = noteguard.run(input_message);
-
-        assert_eq!(output_message.action, Action::Reject);
-    }
-
-    #[test]
-    fn test_blacklist_reject() {
-        let mut noteguard = Noteguard::new();
-
-        let config: Config = toml::from_str(
-            r#"
-            pipeline = ["blacklist"]
-            [filters.blacklist]
-            pubkeys = ["mock_pubkey"]
-        "#,
-        )
-        .expect("Failed to parse config");
-
-        noteguard
-            .load_config(&config)
-            .expect("Failed to load config");
-
-        let input_message = create_mock_input_message("test_event_3", "new");
-        let output_message = noteguard.run(input_message);
-
-        assert_eq!(output_message.action, Action::Reject);
-        assert_eq!(
-            output_message.msg.expect("Failed to get message"),
-            "blocked: pubkey/ip is blacklisted".to_string()
-        );
-    }
-
-    #[test]
-    fn test_blacklist_accept() {
-        let mut noteguard = Noteguard::new();
-
-        let config: Config = toml::from_str(
-            r#"
-            pipeline = ["blacklist"]
-            [filters.blacklist]
-            pubkeys = ["not_blacklisted"]
-        "#,
-        )
-        .expect("Failed to parse config");
-
-        noteguard
-            .load_config(&config)
-            .expect("Failed to load config");
-
-        let input_message = create_mock_input_message("test_event_4", "new");
-        let output_message = noteguard.run(input_message);
-
-        assert_eq!(output_message.action, Action::Accept);
-    }
-
-    #[test]
-    fn test_deserialize_input_message() {
-        let input_json = r#"
-        {
-            "type": "new",
-            "event": {
-                "id": "test_event_5",
-                "pubkey": "mock_pubkey",
-                "created_at": 0,
-                "kind": 1,
-                "tags": [],
-                "content": "mock_content",
-                "sig": "mock_signature"
-            },
-            "receivedAt": 0,
-            "sourceType": "mock_source",
-            "sourceInfo": "mock_source_info"
-        }
-        "#;
-
-        let input_message: InputMessage =
-            serde_json::from_str(input_json).expect("Failed to deserialize input message");
-        assert_eq!(input_message.event.id, "test_event_5");
-        assert_eq!(input_message.message_type, "new");
-    }
+    ExitCode::SUCCESS
 }
@@ -1,5 +1,6 @@
 use crate::Note;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 
 #[derive(Deserialize)]
 pub struct InputMessage {
@@ -14,12 +15,56 @@ pub struct InputMessage {
     pub source_info: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+/// Where strfry got a note from, parsed from `sourceType` and `sourceInfo`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Source {
+    /// A client connected from this address
+    Ip(IpAddr),
+
+    /// `strfry import`
+    Import,
+
+    /// `strfry stream` or `strfry router` from another relay
+    Stream,
+
+    /// `strfry sync` with another relay
+    Sync,
+
+    /// Any other source, or an ip source whose address couldn't be parsed
+    Other,
+}
+
+impl InputMessage {
+    pub fn source(&self) -> Source {
+        match self.source_type.as_str() {
+            "IP4" | "IP6" => self.source_info.parse().map_or(Source::Other, Source::Ip),
+            "Import" => Source::Import,
+            "Stream" => Source::Stream,
+            "Sync" => Source::Sync,
+            _ => Source::Other,
+        }
+    }
+
+    /// The client's address, if the note came from a client connection
+    pub fn source_ip(&self) -> Option<IpAddr> {
+        match self.source() {
+            Source::Ip(addr) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum Action {
     Accept,
     Reject,
     ShadowReject,
+
+    /// Accept the note without running the rest of the pipeline. This is
+    /// sent to strfry as a plain `accept`.
+    #[serde(rename(serialize = "accept"))]
+    AcceptFinal,
 }
 
 #[derive(Serialize)]
@@ -34,4 +79,159 @@ impl OutputMessage {
     pub fn new(id: String, action: Action, msg: Option<String>) -> Self {
         OutputMessage { id, action, msg }
     }
+
+    /// A best-effort rejection for an input line that could not be parsed, so
+    /// that strfry always gets a response. The event id is recovered from the
+    /// line if possible. `None` for `lookback` lines, which strfry doesn't
+    /// expect a response for.
+    pub fn malformed(line: &str, err: &serde_json::Error) -> Option<Self> {
+        let id = match recover_id(line) {
+            Recovered::Lookback => return None,
+            Recovered::Id(id) => id,
+            Recovered::Nothing => String::new(),
+        };
+
+        Some(OutputMessage::new(
+            id,
+            Action::Reject,
+            Some(format!("error: could not parse input: {}", err)),
+        ))
+    }
+}
+
+/// What [`recover_id`] found in a line
+enum Recovered {
+    Id(String),
+    Lookback,
+    Nothing,
+}
+
+/// Find the event id in an input line that isn't a valid [`InputMessage`]
+fn recover_id(line: &str) -> Recovered {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(line) {
+        if value.get("type").and_then(|t| t.as_str()) == Some("lookback") {
+            return Recovered::Lookback;
+        }
+
+        if let Some(id) = value.pointer("/event/id").and_then(|id| id.as_str()) {
+            return Recovered::Id(id.to_owned());
+        }
+    }
+
+    // the line isn't valid json, look for something like "id":"<hex>"
+    let mut rest = line;
+    while let Some(ind) = rest.find("\"id\"") {
+        rest = &rest[ind + 4..];
+
+        let Some(value) = rest.trim_start().strip_prefix(':') else {
+            continue;
+        };
+        let Some(value) = value.trim_start().strip_prefix('"') else {
+            continue;
+        };
+
+        let id: String = value
+            .chars()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .collect();
+        if id.len() == 64 {
+            return Recovered::Id(id);
+        }
+    }
+
+    Recovered::Nothing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_input_message() {
+        let input_json = r#"
+        {
+            "type": "new",
+            "event": {
+                "id": "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76",
+                "pubkey": "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93",
+                "created_at": 0,
+                "kind": 1,
+                "tags": [],
+                "content": "mock_content",
+                "sig": "7b76471744ded2b720ca832cdc89e670f6093ce38aeef55a5c6a4e077883d7d80dda1e9051032fb1faa1c3c212c517e93ee42b3ceac8e8e9b04bad46a361de90"
+            },
+            "receivedAt": 0,
+            "sourceType": "mock_source",
+            "sourceInfo": "mock_source_info"
+        }
+        "#;
+
+        let input_message: InputMessage =
+            serde_json::from_str(input_json).expect("Failed to deserialize input message");
+        assert_eq!(
+            input_message.event.id.to_string(),
+            "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76"
+        );
+        assert_eq!(input_message.message_type, "new");
+    }
+
+    #[test]
+    fn test_serialize_accept_final() {
+        let out = OutputMessage::new("test_event_6".to_string(), Action::AcceptFinal, None);
+        assert_eq!(
+            serde_json::to_string(&out).expect("Failed to serialize output message"),
+            r#"{"id":"test_event_6","action":"accept"}"#
+        );
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let id = "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76";
+
+        // valid json, but not a valid input message
+        let line = format!(
+            r#"{{"type": "new", "event": {{"id": "{}", "kind": "one"}}}}"#,
+            id
+        );
+        let err = serde_json::from_str::<InputMessage>(&line)
+            .err()
+            .expect("input should not parse");
+        let out = OutputMessage::malformed(&line, &err).expect("expected a response");
+        assert_eq!(out.id, id);
+        assert_eq!(out.action, Action::Reject);
+        assert!(out
+            .msg
+            .as_deref()
+            .unwrap()
+            .starts_with("error: could not parse input:"));
+
+        // truncated json
+        let line = format!(r#"{{"type": "new", "event": {{"id" : "{}", "pubkey": "#, id);
+        let err = serde_json::from_str::<InputMessage>(&line)
+            .err()
+            .expect("input should not parse");
+        assert_eq!(
+            OutputMessage::malformed(&line, &err).map(|out| out.id),
+            Some(id.to_string())
+        );
+
+        // strfry doesn't expect a response to lookback lines
+        let line = format!(
+            r#"{{"type": "lookback", "event": {{"id": "{}", "kind": "one"}}}}"#,
+            id
+        );
+        let err = serde_json::from_str::<InputMessage>(&line)
+            .err()
+            .expect("input should not parse");
+        assert!(OutputMessage::malformed(&line, &err).is_none());
+
+        let line = "garbage";
+        let err = serde_json::from_str::<InputMessage>(line)
+            .err()
+            .expect("input should not parse");
+        assert_eq!(
+            OutputMessage::malformed(line, &err).map(|out| out.id),
+            Some(String::new())
+        );
+    }
 }
@@ -0,0 +1,379 @@
+use crate::{Action, Decision, InputMessage, Kind, MetricsConfig};
+use log::{debug, error, info};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+/// Upper bounds of the filter latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5,
+];
+
+#[derive(Default)]
+struct Histogram {
+    /// Cumulative counts for each of [`LATENCY_BUCKETS`]
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= le {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Default)]
+struct MetricsState {
+    /// Keyed by sourceType and kind
+    events: BTreeMap<(String, Kind), u64>,
+
+    /// Keyed by filter name and action
+    decisions: BTreeMap<(String, &'static str), u64>,
+
+    /// Keyed by filter name
+    latency: BTreeMap<String, Histogram>,
+
+    /// Keyed by gauge name and filter name, see [`crate::NoteFilter::gauges`]
+    gauges: BTreeMap<(&'static str, String), u64>,
+}
+
+/// Counters and histograms of what the pipeline is doing, rendered in the
+/// Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+fn action_label(action: Action) -> &'static str {
+    match action {
+        Action::Accept => "accept",
+        Action::Reject => "reject",
+        Action::ShadowReject => "shadowReject",
+        Action::AcceptFinal => "acceptFinal",
+    }
+}
+
+/// Escape a label value, see the Prometheus text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn observe(&self, input: &InputMessage, decision: &Decision) {
+        let mut state = self.state.lock().expect("metrics lock poisoned");
+
+        *state
+            .events
+            .entry((input.source_type.clone(), input.event.kind))
+            .or_default() += 1;
+
+        for timing in &decision.timings {
+            *state
+                .decisions
+                .entry((timing.name.clone(), action_label(timing.action)))
+                .or_default() += 1;
+
+            state
+                .latency
+                .entry(timing.name.clone())
+                .or_default()
+                .observe(timing.micros as f64 / 1_000_000.0);
+        }
+    }
+
+    pub fn set_gauge(&self, gauge: &'static str, filter: &str, value: u64) {
+        let mut state = self.state.lock().expect("metrics lock poisoned");
+        state.gauges.insert((gauge, filter.to_owned()), value);
+    }
+
+    /// Forget every gauge, eg: when the filters that reported them are
+    /// replaced by a new config
+    pub fn clear_gauges(&self) {
+        let mut state = self.state.lock().expect("metrics lock poisoned");
+        state.gauges.clear();
+    }
+
+    pub fn render(&self) -> String {
+        let state = self.state.lock().expect("metrics lock poisoned");
+        let mut out = String::new();
+
+        out.push_str("# HELP noteguard_events_total Notes seen, by sourceType and kind\n");
+        out.push_str("# TYPE noteguard_events_total counter\n");
+        for ((source_type, kind), count) in &state.events {
+            let _ = writeln!(
+                out,
+                "noteguard_events_total{{source_type=\"{}\",kind=\"{}\"}} {}",
+                escape(source_type),
+                kind,
+                count
+            );
+        }
+
+        out.push_str(
+            "# HELP noteguard_filter_decisions_total Filter decisions, by filter and action\n",
+        );
+        out.push_str("# TYPE noteguard_filter_decisions_total counter\n");
+        for ((filter, action), count) in &state.decisions {
+            let _ = writeln!(
+                out,
+                "noteguard_filter_decisions_total{{filter=\"{}\",action=\"{}\"}} {}",
+                escape(filter),
+                action,
+                count
+            );
+        }
+
+        out.push_str("# HELP noteguard_filter_duration_seconds Time spent in each filter\n");
+        out.push_str("# TYPE noteguard_filter_duration_seconds histogram\n");
+        for (filter, histogram) in &state.latency {
+            let filter = escape(filter);
+            for (count, le) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "noteguard_filter_duration_seconds_bucket{{filter=\"{}\",le=\"{}\"}} {}",
+                    filter, le, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "noteguard_filter_duration_seconds_bucket{{filter=\"{}\",le=\"+Inf\"}} {}",
+                filter, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "noteguard_filter_duration_seconds_sum{{filter=\"{}\"}} {}",
+                filter, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "noteguard_filter_duration_seconds_count{{filter=\"{}\"}} {}",
+                filter, histogram.count
+            );
+        }
+
+        let mut last_gauge = None;
+        for ((gauge, filter), value) in &state.gauges {
+            if last_gauge != Some(gauge) {
+                let _ = writeln!(out, "# TYPE noteguard_{} gauge", gauge);
+                last_gauge = Some(gauge);
+            }
+            let _ = writeln!(
+                out,
+                "noteguard_{}{{filter=\"{}\"}} {}",
+                gauge,
+                escape(filter),
+                value
+            );
+        }
+
+        out
+    }
+
+    /// Serve metrics on the configured listener in a background thread
+    pub fn serve(self: &Arc<Self>, config: &MetricsConfig) -> io::Result<()> {
+        #[cfg(unix)]
+        if let Some(path) = config.listen.strip_prefix("unix:") {
+            // remove a stale socket from a previous run
+            let _ = std::fs::remove_file(path);
+            self.serve_unix(UnixListener::bind(path)?);
+            info!("serving metrics on {}", config.listen);
+            return Ok(());
+        }
+
+        self.serve_tcp(TcpListener::bind(&config.listen)?);
+        info!("serving metrics on {}", config.listen);
+        Ok(())
+    }
+
+    pub fn serve_tcp(self: &Arc<Self>, listener: TcpListener) {
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(mut stream) => {
+                        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+                        let reader = stream.try_clone().map(BufReader::new);
+                        if let Ok(reader) = reader {
+                            metrics.respond(reader, &mut stream);
+                        }
+                    }
+                    Err(err) => error!("metrics connection failed: {}", err),
+                }
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    pub fn serve_unix(self: &Arc<Self>, listener: UnixListener) {
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(mut stream) => {
+                        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+                        let reader = stream.try_clone().map(BufReader::new);
+                        if let Ok(reader) = reader {
+                            metrics.respond(reader, &mut stream);
+                        }
+                    }
+                    Err(err) => error!("metrics connection failed: {}", err),
+                }
+            }
+        });
+    }
+
+    /// Answer any HTTP request with the current metrics
+    fn respond(&self, mut reader: BufReader<impl Read>, writer: &mut impl Write) {
+        // skip the request line and headers
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) if line.trim_end().is_empty() => break,
+                Ok(_) => continue,
+                Err(err) => {
+                    debug!("could not read metrics request: {}", err);
+                    return;
+                }
+            }
+        }
+
+        let body = self.render();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        if let Err(err) = writer.write_all(response.as_bytes()) {
+            debug!("could not write metrics response: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Note, NoteId, Noteguard, Signature, Tags};
+    use std::net::TcpStream;
+
+    fn create_mock_input_message(event_id: u8, kind: u16) -> InputMessage {
+        InputMessage {
+            message_type: "new".to_string(),
+            event: Note {
+                id: NoteId::from_bytes([event_id; 32]),
+                pubkey: "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93"
+                    .parse()
+                    .unwrap(),
+                created_at: 0,
+                kind: Kind(kind),
+                tags: Tags::default(),
+                content: "mock_content".to_string(),
+                sig: Signature::from_bytes([0; 64]),
+            },
+            received_at: 0,
+            source_type: "IP4".to_string(),
+            source_info: "127.0.0.1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_scrape_metrics() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["ratelimit", "kinds"]
+
+                [filters.ratelimit]
+                posts_per_minute = 10
+
+                [filters.kinds]
+                kinds = [7]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        noteguard.run(create_mock_input_message(1, 1));
+        noteguard.run(create_mock_input_message(2, 7));
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind");
+        let addr = listener.local_addr().expect("Failed to get address");
+        noteguard.metrics().serve_tcp(listener);
+
+        let mut stream = TcpStream::connect(addr).expect("Failed to connect");
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .expect("Failed to send request");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("Failed to read response");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("noteguard_events_total{source_type=\"IP4\",kind=\"1\"} 1\n"));
+        assert!(response.contains("noteguard_events_total{source_type=\"IP4\",kind=\"7\"} 1\n"));
+        assert!(response
+            .contains("noteguard_filter_decisions_total{filter=\"kinds\",action=\"reject\"} 1\n"));
+        assert!(response.contains(
+            "noteguard_filter_decisions_total{filter=\"ratelimit\",action=\"accept\"} 2\n"
+        ));
+        assert!(response.contains("noteguard_filter_duration_seconds_count{filter=\"kinds\"} 2\n"));
+        assert!(response.contains("noteguard_ratelimit_buckets{filter=\"ratelimit\"} 1\n"));
+    }
+
+    #[test]
+    fn test_gauges() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["guard"]
+
+                [filters.guard]
+                type = "any"
+                filters = ["ratelimit"]
+
+                [filters.ratelimit]
+                posts_per_minute = 10
+            "#,
+            )
+            .expect("Failed to load config");
+
+        noteguard.run(create_mock_input_message(1, 1));
+        let rendered = noteguard.metrics().render();
+        assert!(rendered.contains("noteguard_ratelimit_buckets{filter=\"guard\"} 1\n"));
+
+        // gauges of filters that are no longer loaded go away
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["kinds"]
+
+                [filters.kinds]
+                kinds = [7]
+            "#,
+            )
+            .expect("Failed to load config");
+        noteguard.run(create_mock_input_message(2, 1));
+        assert!(!noteguard.metrics().render().contains("ratelimit_buckets"));
+    }
+}
@@ -0,0 +1,395 @@
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// Fixed size byte arrays that are hex encoded in notes and configs
+macro_rules! hex_bytes {
+    ($(#[$meta:meta])* $name:ident, $len:expr, $expecting:expr) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name([u8; $len]);
+
+        impl $name {
+            pub fn from_bytes(bytes: [u8; $len]) -> Self {
+                $name(bytes)
+            }
+
+            pub fn as_bytes(&self) -> &[u8; $len] {
+                &self.0
+            }
+
+            pub fn to_hex(&self) -> String {
+                hex::encode(self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = hex::FromHexError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let mut bytes = [0; $len];
+                hex::decode_to_slice(s, &mut bytes)?;
+                Ok($name(bytes))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.to_hex())
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self.to_hex())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct HexVisitor;
+
+                impl Visitor<'_> for HexVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        f.write_str($expecting)
+                    }
+
+                    fn visit_str<E: de::Error>(self, s: &str) -> Result<$name, E> {
+                        s.parse()
+                            .map_err(|err| E::custom(format!("invalid {}: {}", $expecting, err)))
+                    }
+                }
+
+                deserializer.deserialize_str(HexVisitor)
+            }
+        }
+    };
+}
+
+hex_bytes!(
+    /// A 32-byte note id
+    NoteId,
+    32,
+    "a hex encoded 32-byte note id"
+);
+
+impl NoteId {
+    /// The NIP-13 proof of work difficulty, the number of leading zero bits
+    pub fn difficulty(&self) -> u32 {
+        let mut bits = 0;
+        for byte in self.0 {
+            bits += byte.leading_zeros();
+            if byte != 0 {
+                break;
+            }
+        }
+        bits
+    }
+}
+
+hex_bytes!(
+    /// A 32-byte x-only public key
+    Pubkey,
+    32,
+    "a hex encoded 32-byte pubkey"
+);
+
+hex_bytes!(
+    /// A 64-byte Schnorr signature
+    Signature,
+    64,
+    "a hex encoded 64-byte signature"
+);
+
+/// How relays store notes of a kind, see NIP-01
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KindClass {
+    Regular,
+    Replaceable,
+    Ephemeral,
+    Addressable,
+}
+
+/// A note kind
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct Kind(pub u16);
+
+impl Kind {
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// Kinds that don't fall in any of the NIP-01 ranges are regular
+    pub fn class(&self) -> KindClass {
+        match self.0 {
+            0 | 3 | 10000..=19999 => KindClass::Replaceable,
+            20000..=29999 => KindClass::Ephemeral,
+            30000..=39999 => KindClass::Addressable,
+            _ => KindClass::Regular,
+        }
+    }
+
+    pub fn is_regular(&self) -> bool {
+        self.class() == KindClass::Regular
+    }
+
+    pub fn is_replaceable(&self) -> bool {
+        self.class() == KindClass::Replaceable
+    }
+
+    pub fn is_ephemeral(&self) -> bool {
+        self.class() == KindClass::Ephemeral
+    }
+
+    pub fn is_addressable(&self) -> bool {
+        self.class() == KindClass::Addressable
+    }
+}
+
+impl From<u16> for Kind {
+    fn from(kind: u16) -> Self {
+        Kind(kind)
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A single tag, eg: `["p", "<pubkey>", "<relay>"]`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Tag(pub Vec<String>);
+
+impl Tag {
+    /// The first element of the tag
+    pub fn name(&self) -> Option<&str> {
+        self.get(0)
+    }
+
+    /// The second element of the tag
+    pub fn value(&self) -> Option<&str> {
+        self.get(1)
+    }
+
+    pub fn get(&self, ind: usize) -> Option<&str> {
+        self.0.get(ind).map(|s| s.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|s| s.as_str())
+    }
+}
+
+/// A NIP-01 `a` tag coordinate, `<kind>:<pubkey>:<d tag>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Address {
+    pub kind: Kind,
+    pub pubkey: Pubkey,
+    pub identifier: String,
+}
+
+impl FromStr for Address {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let kind = parts.next().and_then(|k| k.parse().ok()).ok_or(())?;
+        let pubkey = parts.next().and_then(|p| p.parse().ok()).ok_or(())?;
+        let identifier = parts.next().unwrap_or("").to_owned();
+
+        Ok(Address {
+            kind: Kind(kind),
+            pubkey,
+            identifier,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Tags(pub Vec<Tag>);
+
+impl Tags {
+    pub fn iter(&self) -> impl Iterator<Item = &Tag> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Tags whose first element is `name`
+    pub fn tags_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Tag> {
+        self.0.iter().filter(move |tag| tag.name() == Some(name))
+    }
+
+    /// The values of the tags named `name`
+    pub fn tag_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.tags_named(name).filter_map(|tag| tag.value())
+    }
+
+    pub fn first_tag_value(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|tag| tag.name() == Some(name))
+            .and_then(|tag| tag.value())
+    }
+
+    pub fn has_tag(&self, name: &str) -> bool {
+        self.tags_named(name).next().is_some()
+    }
+
+    /// Note ids referenced by `e` tags. Malformed ids are skipped.
+    pub fn e_tags(&self) -> impl Iterator<Item = NoteId> + '_ {
+        self.tag_values("e").filter_map(|id| id.parse().ok())
+    }
+
+    /// Pubkeys referenced by `p` tags. Malformed pubkeys are skipped.
+    pub fn p_tags(&self) -> impl Iterator<Item = Pubkey> + '_ {
+        self.tag_values("p").filter_map(|pk| pk.parse().ok())
+    }
+
+    /// Addresses referenced by `a` tags. Malformed addresses are skipped.
+    pub fn a_tags(&self) -> impl Iterator<Item = Address> + '_ {
+        self.tag_values("a").filter_map(|a| a.parse().ok())
+    }
+
+    /// Hashtags
+    pub fn t_tags(&self) -> impl Iterator<Item = &str> {
+        self.tag_values("t")
+    }
+
+    /// The NIP-40 expiration timestamp
+    pub fn expiration(&self) -> Option<u64> {
+        self.first_tag_value("expiration")?.parse().ok()
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Note {
+    pub id: NoteId,
+    pub pubkey: Pubkey,
+    pub content: String,
+    pub created_at: i64,
+    pub kind: Kind,
+    pub tags: Tags,
+    pub sig: Signature,
+}
+
+impl Note {
+    /// The NIP-01 event id, the sha256 of the canonical serialization
+    /// `[0,pubkey,created_at,kind,tags,content]`
+    pub fn compute_id(&self) -> NoteId {
+        let canonical = json!([
+            0,
+            self.pubkey,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content
+        ]);
+
+        NoteId(Sha256::digest(canonical.to_string().as_bytes()).into())
+    }
+
+    /// The NIP-40 expiration timestamp
+    pub fn expiration(&self) -> Option<u64> {
+        self.tags.expiration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_class() {
+        assert_eq!(Kind(1).class(), KindClass::Regular);
+        assert_eq!(Kind(0).class(), KindClass::Replaceable);
+        assert_eq!(Kind(10002).class(), KindClass::Replaceable);
+        assert_eq!(Kind(20001).class(), KindClass::Ephemeral);
+        assert_eq!(Kind(30023).class(), KindClass::Addressable);
+        assert_eq!(Kind(40000).class(), KindClass::Regular);
+    }
+
+    #[test]
+    fn test_tags() {
+        let pubkey = "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93";
+        let tags: Tags = serde_json::from_value(json!([
+            ["-"],
+            ["p", pubkey, "wss://relay.damus.io"],
+            ["p", "not a pubkey"],
+            ["d", "my-article"],
+            ["t", "nostr"],
+            ["a", format!("30023:{}:my-article", pubkey)],
+            ["expiration", "1722305142"]
+        ]))
+        .expect("Failed to parse tags");
+
+        assert!(tags.has_tag("-"));
+        assert!(!tags.has_tag("e"));
+        assert_eq!(tags.tags_named("p").count(), 2);
+        assert_eq!(
+            tags.p_tags().collect::<Vec<_>>(),
+            vec![pubkey.parse().unwrap()]
+        );
+        assert_eq!(tags.first_tag_value("d"), Some("my-article"));
+        assert_eq!(tags.t_tags().collect::<Vec<_>>(), vec!["nostr"]);
+        assert_eq!(tags.expiration(), Some(1722305142));
+
+        let address = tags.a_tags().next().expect("expected an address");
+        assert_eq!(address.kind, Kind(30023));
+        assert_eq!(address.identifier, "my-article");
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let id: NoteId = "000006d8c378af1779d2feebc7603a125d99eca0ccf1085959b307f64e5dd358"
+            .parse()
+            .expect("invalid id");
+        assert_eq!(id.difficulty(), 21);
+        assert_eq!(NoteId::from_bytes([0; 32]).difficulty(), 256);
+        assert_eq!(NoteId::from_bytes([0xff; 32]).difficulty(), 0);
+    }
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let id = "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76";
+        let note_id: NoteId = serde_json::from_str(&format!("\"{}\"", id)).expect("invalid id");
+        assert_eq!(note_id.to_string(), id);
+        assert_eq!(
+            serde_json::to_string(&note_id).expect("Failed to serialize"),
+            format!("\"{}\"", id)
+        );
+
+        assert!("abcd".parse::<NoteId>().is_err());
+        assert!(serde_json::from_str::<Pubkey>("\"mock_pubkey\"").is_err());
+    }
+}
@@ -1,20 +1,25 @@
 use crate::{InputMessage, OutputMessage};
-use serde::{Deserialize, Serialize};
-
-#[derive(Deserialize, Serialize, Clone)]
-pub struct Note {
-    pub id: String,
-    pub pubkey: String,
-    pub content: String,
-    pub created_at: i64,
-    pub kind: i64,
-    pub tags: Vec<Vec<String>>,
-    pub sig: String,
-}
 
 pub trait NoteFilter {
     fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage;
 
-    /// A key corresponding to an entry in the noteguard.toml file.
+    /// Called for strfry `lookback` messages, which are notes that were
+    /// already accepted before noteguard started. Stateful filters can use
+    /// this to warm up their state. Filters with side effects, such as
+    /// forwarding, should do nothing here, which is the default.
+    fn observe(&mut self, _msg: &InputMessage) {}
+
+    /// Called after the whole pipeline accepted a `new` note, so filters
+    /// can learn from notes that other filters didn't reject
+    fn accepted(&mut self, _msg: &InputMessage) {}
+
+    /// Gauges describing the filter's internal state, exposed as
+    /// `noteguard_<name>` metrics, eg: `("ratelimit_buckets", 12)`
+    fn gauges(&self) -> Vec<(&'static str, u64)> {
+        vec![]
+    }
+
+    /// The filter type, used to match `[filters.<name>]` entries in the
+    /// noteguard.toml file. See [`crate::Config`].
     fn name(&self) -> &'static str;
 }
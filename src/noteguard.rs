@@ -0,0 +1,1844 @@
+use crate::config::{deserialize_settings, SettingsError};
+use crate::filters::{
+    Blacklist, Combinator, CombinatorConfig, CombinatorKind, Content, Expiration, Kinds, Limits,
+    MediaHashes, Pow, ProtectedEvents, RateLimit, SharesThrottled, Throttled, Timestamps, Urls,
+    Verify, Whitelist,
+};
+use crate::AuditLog;
+use crate::{
+    Action, Config, ConfigError, FilterMode, InputMessage, NoteFilter, OutputMessage, Route,
+};
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+use crate::{Metrics, MetricsConfig};
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+
+#[cfg(feature = "forwarder")]
+use crate::filters::Forwarder;
+
+type ConstructFilter =
+    Box<fn(toml::Value, &Throttled) -> Result<Box<dyn NoteFilter>, SettingsError>>;
+
+/// A filter in the pipeline, along with the config it was loaded from
+struct LoadedFilter {
+    name: String,
+
+    /// `None` for filters added in code, these are never carried over
+    /// by [`Noteguard::reload_config`]
+    config: Option<toml::Value>,
+
+    mode: FilterMode,
+
+    filter: Box<dyn NoteFilter>,
+}
+
+/// A route, along with the filters it runs as indices into
+/// [`Noteguard::loaded_filters`]
+struct LoadedRoute {
+    route: Route,
+    pipeline: Vec<usize>,
+}
+
+/// The filters loaded from a config. Filters used by more than one pipeline
+/// are only loaded once, so they share state.
+#[derive(Default)]
+struct LoadedConfig {
+    filters: Vec<LoadedFilter>,
+    pipeline: Vec<usize>,
+    routes: Vec<LoadedRoute>,
+    audit: Option<AuditLog>,
+}
+
+/// How long a filter took to run, and what it decided
+#[derive(Serialize, Debug, Clone)]
+pub struct FilterTiming {
+    pub name: String,
+    pub action: Action,
+
+    /// The filter is in monitor mode, so its action was not applied
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub monitor: bool,
+
+    pub micros: u64,
+}
+
+/// The result of running a note through the pipeline, see
+/// [`Noteguard::evaluate`]
+pub struct Decision {
+    pub output: OutputMessage,
+
+    /// The filter that rejected or finally accepted the note. `None` if
+    /// the note made it through the whole pipeline.
+    pub filter: Option<String>,
+
+    /// Every filter that was run, in order
+    pub timings: Vec<FilterTiming>,
+}
+
+/// A filter pipeline. Filters are run in order until one of them rejects
+/// the note, or accepts it with [`Action::AcceptFinal`].
+#[derive(Default)]
+pub struct Noteguard {
+    registered_filters: HashMap<String, ConstructFilter>,
+    loaded_filters: Vec<LoadedFilter>,
+
+    /// The default pipeline, as indices into `loaded_filters`
+    pipeline: Vec<usize>,
+
+    routes: Vec<LoadedRoute>,
+
+    audit: Option<AuditLog>,
+
+    /// Sources and pubkeys throttled by this noteguard's ratelimits
+    throttled: Throttled,
+
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>,
+
+    /// Where metrics are currently served
+    #[cfg(feature = "metrics")]
+    metrics_config: Option<MetricsConfig>,
+}
+
+impl Noteguard {
+    pub fn new() -> Self {
+        let mut noteguard = Noteguard::default();
+        noteguard.register_builtin_filters();
+        noteguard
+    }
+
+    /// Construct a pipeline in code, see [`NoteguardBuilder`]
+    pub fn builder() -> NoteguardBuilder {
+        NoteguardBuilder::default()
+    }
+
+    pub fn register_filter<F: NoteFilter + 'static + Default + DeserializeOwned>(&mut self) {
+        self.registered_filters.insert(
+            F::name(&F::default()).to_string(),
+            Box::new(|filter_config, _throttled| {
+                deserialize_settings(filter_config)
+                    .map(|filter: F| Box::new(filter) as Box<dyn NoteFilter>)
+            }),
+        );
+    }
+
+    /// Register a builtin filter that shares the sources and pubkeys
+    /// throttled by this noteguard's ratelimits
+    fn register_throttled_filter<
+        F: NoteFilter + SharesThrottled + 'static + Default + DeserializeOwned,
+    >(
+        &mut self,
+    ) {
+        self.registered_filters.insert(
+            F::name(&F::default()).to_string(),
+            Box::new(|filter_config, throttled| {
+                deserialize_settings(filter_config).map(|mut filter: F| {
+                    filter.set_throttled(throttled);
+                    Box::new(filter) as Box<dyn NoteFilter>
+                })
+            }),
+        );
+    }
+
+    /// All builtin filters are registered here, and are made available with
+    /// every new instance of [`Noteguard`]
+    fn register_builtin_filters(&mut self) {
+        self.register_throttled_filter::<RateLimit>();
+        self.register_filter::<Whitelist>();
+        self.register_filter::<Blacklist>();
+        self.register_filter::<ProtectedEvents>();
+        self.register_filter::<Kinds>();
+        self.register_filter::<Content>();
+        self.register_filter::<Verify>();
+        self.register_filter::<Timestamps>();
+        self.register_filter::<Expiration>();
+        self.register_throttled_filter::<Pow>();
+        self.register_filter::<Limits>();
+        self.register_filter::<Urls>();
+        self.register_filter::<MediaHashes>();
+
+        #[cfg(feature = "forwarder")]
+        self.register_filter::<Forwarder>();
+    }
+
+    /// Append an already constructed filter to the end of the pipeline
+    pub fn push_filter(&mut self, filter: Box<dyn NoteFilter>) {
+        self.pipeline.push(self.loaded_filters.len());
+        self.loaded_filters.push(LoadedFilter {
+            name: filter.name().to_owned(),
+            config: None,
+            mode: FilterMode::Enforce,
+            filter,
+        });
+    }
+
+    /// The number of filters in the default pipeline
+    pub fn len(&self) -> usize {
+        self.pipeline.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipeline.is_empty()
+    }
+
+    /// Run the loaded filters of the first matching route, or of the default
+    /// pipeline. You must call `load_config` before calling this, otherwise
+    /// not filters will be run.
+    pub fn run(&mut self, input: InputMessage) -> OutputMessage {
+        let decision = self.evaluate(&input);
+
+        #[cfg(feature = "metrics")]
+        self.record_metrics(&input, &decision);
+
+        self.write_audit(|audit| audit.write(&input, &decision));
+
+        decision.output
+    }
+
+    /// Write to the audit log, if there is one. Errors are logged, a broken
+    /// audit log doesn't stop notes from being handled.
+    fn write_audit(&mut self, write: impl FnOnce(&mut AuditLog) -> std::io::Result<()>) {
+        if let Some(audit) = &mut self.audit {
+            if let Err(err) = write(audit) {
+                error!(
+                    "could not write to audit log {}: {}",
+                    audit.config().path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record_metrics(&self, input: &InputMessage, decision: &Decision) {
+        self.metrics.observe(input, decision);
+
+        for loaded in &self.loaded_filters {
+            for (gauge, value) in loaded.filter.gauges() {
+                self.metrics.set_gauge(gauge, &loaded.name, value);
+            }
+        }
+    }
+
+    /// Start serving metrics if they are configured and not served yet
+    fn start_metrics(&mut self, config: &Config) -> Result<(), ConfigError> {
+        #[cfg(feature = "metrics")]
+        {
+            let Some(metrics_config) = &config.metrics else {
+                return Ok(());
+            };
+
+            match &self.metrics_config {
+                Some(serving) if serving == metrics_config => {}
+                Some(serving) => warn!(
+                    "metrics are still served on {}, restart noteguard to serve them on {}",
+                    serving.listen, metrics_config.listen
+                ),
+                None => {
+                    self.metrics
+                        .serve(metrics_config)
+                        .map_err(|source| ConfigError::Metrics {
+                            listen: metrics_config.listen.clone(),
+                            source,
+                        })?;
+                    self.metrics_config = Some(metrics_config.clone());
+                }
+            }
+        }
+
+        #[cfg(not(feature = "metrics"))]
+        if config.metrics.is_some() {
+            warn!("[metrics] is set, but noteguard was built without the metrics feature");
+        }
+
+        Ok(())
+    }
+
+    /// Feed a note that was already accepted to the filters of its pipeline,
+    /// without making a decision, see [`NoteFilter::observe`]
+    pub fn observe(&mut self, input: &InputMessage) {
+        let pipeline = self
+            .routes
+            .iter()
+            .find(|loaded| loaded.route.matches(input))
+            .map_or(&self.pipeline, |loaded| &loaded.pipeline);
+
+        for &ind in pipeline {
+            self.loaded_filters[ind].filter.observe(input);
+        }
+    }
+
+    /// Handle a line of strfry write policy input. Every line gets a
+    /// response, except for `lookback` messages, which strfry does not
+    /// expect a response for, even if they can't be parsed.
+    pub fn handle_line(&mut self, line: &str) -> Option<OutputMessage> {
+        let input: InputMessage = match serde_json::from_str(line) {
+            Ok(input) => input,
+            Err(err) => {
+                warn!("could not parse input: {}", err);
+                let output = OutputMessage::malformed(line, &err)?;
+                self.write_audit(|audit| audit.write_malformed(&output));
+                return Some(output);
+            }
+        };
+
+        match input.message_type.as_str() {
+            "new" => Some(self.run(input)),
+            "lookback" => {
+                self.observe(&input);
+                None
+            }
+            _ => {
+                let decision = Decision {
+                    output: OutputMessage::new(
+                        input.event.id.to_string(),
+                        Action::Reject,
+                        Some("invalid strfry write policy input".to_string()),
+                    ),
+                    filter: Some("input".to_string()),
+                    timings: vec![],
+                };
+                self.write_audit(|audit| audit.write(&input, &decision));
+                Some(decision.output)
+            }
+        }
+    }
+
+    /// Like [`Noteguard::run`], but returns the details of how the decision
+    /// was made, and does not write to the audit log
+    pub fn evaluate(&mut self, input: &InputMessage) -> Decision {
+        let mut mout: Option<OutputMessage> = None;
+        let mut timings = vec![];
+
+        let pipeline = self
+            .routes
+            .iter()
+            .find(|loaded| loaded.route.matches(input))
+            .map_or(&self.pipeline, |loaded| &loaded.pipeline);
+
+        let id = input.event.id.to_string();
+        for &ind in pipeline {
+            let loaded = &mut self.loaded_filters[ind];
+
+            let start = Instant::now();
+            let out = loaded.filter.filter_note(input);
+            timings.push(FilterTiming {
+                name: loaded.name.clone(),
+                action: out.action,
+                monitor: loaded.mode == FilterMode::Monitor,
+                micros: start.elapsed().as_micros() as u64,
+            });
+
+            if loaded.mode == FilterMode::Monitor && out.action != Action::Accept {
+                info!(
+                    "monitor: {} would {:?} {}: {}",
+                    loaded.name,
+                    out.action,
+                    id,
+                    out.msg.as_deref().unwrap_or("")
+                );
+                continue;
+            }
+
+            match out.action {
+                Action::Accept => {
+                    mout = Some(out);
+                    continue;
+                }
+                Action::AcceptFinal => {
+                    let filter = Some(loaded.name.clone());
+                    for &ind in pipeline {
+                        self.loaded_filters[ind].filter.accepted(input);
+                    }
+                    return Decision {
+                        output: out,
+                        filter,
+                        timings,
+                    };
+                }
+                Action::Reject | Action::ShadowReject => {
+                    return Decision {
+                        output: out,
+                        filter: Some(loaded.name.clone()),
+                        timings,
+                    };
+                }
+            }
+        }
+
+        for &ind in pipeline {
+            self.loaded_filters[ind].filter.accepted(input);
+        }
+
+        Decision {
+            output: mout.unwrap_or_else(|| OutputMessage::new(id, Action::Accept, None)),
+            filter: None,
+            timings,
+        }
+    }
+
+    /// Initializes a noteguard config. Every pipeline entry must have a
+    /// matching filter configuration and registered filter. If loading
+    /// fails, the previously loaded pipeline is left untouched.
+    pub fn load_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        let loaded = self.load_filters(config)?;
+        self.start_metrics(config)?;
+        self.set_loaded(loaded);
+        Ok(())
+    }
+
+    /// Like [`Noteguard::load_config`], but filters whose settings did not
+    /// change keep their state (eg: ratelimit buckets) instead of being
+    /// replaced with a fresh instance.
+    pub fn reload_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        let mut loaded = self.load_filters(config)?;
+        self.start_metrics(config)?;
+
+        if let Some(audit) = self.audit.take() {
+            if config.audit.as_ref() == Some(audit.config()) {
+                loaded.audit = Some(audit);
+            }
+        }
+
+        let mut old_filters = std::mem::take(&mut self.loaded_filters);
+
+        for loaded in &mut loaded.filters {
+            let unchanged = old_filters.iter().position(|old| {
+                old.config.is_some() && old.name == loaded.name && old.config == loaded.config
+            });
+
+            if let Some(ind) = unchanged {
+                loaded.filter = old_filters.swap_remove(ind).filter;
+            }
+        }
+
+        self.set_loaded(loaded);
+        Ok(())
+    }
+
+    fn set_loaded(&mut self, loaded: LoadedConfig) {
+        #[cfg(feature = "metrics")]
+        self.metrics.clear_gauges();
+
+        self.loaded_filters = loaded.filters;
+        self.pipeline = loaded.pipeline;
+        self.routes = loaded.routes;
+        self.audit = loaded.audit;
+    }
+
+    fn load_filters(&self, config: &Config) -> Result<LoadedConfig, ConfigError> {
+        if let Some(err) = config.check_lists().into_iter().next() {
+            return Err(err);
+        }
+
+        let names = config.pipeline_names();
+        let mut filters = Vec::with_capacity(names.len());
+
+        for name in &names {
+            let (filter, mode) = self.load_filter(config, name)?;
+            filters.push(LoadedFilter {
+                name: name.to_string(),
+                config: resolved_config(config, name),
+                mode,
+                filter,
+            });
+        }
+
+        let indices = |pipeline: &[String]| -> Vec<usize> {
+            pipeline
+                .iter()
+                .filter_map(|name| names.iter().position(|n| n == name))
+                .collect()
+        };
+
+        let pipeline = indices(&config.pipeline);
+        let routes = config
+            .routes
+            .iter()
+            .map(|route| LoadedRoute {
+                route: route.clone(),
+                pipeline: indices(&route.pipeline),
+            })
+            .collect();
+
+        for name in unused_filters(config) {
+            warn!("[filters.{}] is not used by the pipeline", name);
+        }
+
+        let audit = config
+            .audit
+            .as_ref()
+            .map(|audit| {
+                AuditLog::open(audit.clone()).map_err(|source| ConfigError::Audit {
+                    path: audit.path.clone(),
+                    source,
+                })
+            })
+            .transpose()?;
+
+        Ok(LoadedConfig {
+            filters,
+            pipeline,
+            routes,
+            audit,
+        })
+    }
+
+    /// Validate a config without loading it. Unlike [`Noteguard::load_config`],
+    /// this reports every problem found, including filter sections that are
+    /// not used by the pipeline.
+    pub fn check_config(&self, config: &Config) -> Vec<ConfigError> {
+        let mut errors = config.check_lists();
+
+        errors.extend(
+            config
+                .pipeline_names()
+                .into_iter()
+                .filter_map(|name| self.load_filter(config, name).err()),
+        );
+
+        errors.extend(
+            unused_filters(config)
+                .into_iter()
+                .map(|name| ConfigError::UnusedFilter { name }),
+        );
+
+        errors
+    }
+
+    fn load_filter(
+        &self,
+        config: &Config,
+        name: &str,
+    ) -> Result<(Box<dyn NoteFilter>, FilterMode), ConfigError> {
+        self.load_nested_filter(config, name, &mut vec![])
+    }
+
+    /// Load a filter, along with the filters it is made of if it is a
+    /// combinator. `parents` are the combinators currently being loaded.
+    fn load_nested_filter(
+        &self,
+        config: &Config,
+        name: &str,
+        parents: &mut Vec<String>,
+    ) -> Result<(Box<dyn NoteFilter>, FilterMode), ConfigError> {
+        if parents.iter().any(|parent| parent == name) {
+            return Err(ConfigError::FilterCycle {
+                name: name.to_owned(),
+            });
+        }
+
+        let mut config_value =
+            config
+                .filters
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ConfigError::MissingFilter {
+                    name: name.to_owned(),
+                })?;
+
+        let filter_type = filter_type(name, &mut config_value)?;
+        let mode = filter_mode(name, &mut config_value)?;
+        config.substitute_lists(name, &filter_type, &mut config_value)?;
+
+        if let Some(kind) = CombinatorKind::from_type(&filter_type) {
+            let combinator_config: CombinatorConfig =
+                deserialize_settings(config_value).map_err(|err| invalid_filter(name, err))?;
+
+            let expected = match kind {
+                CombinatorKind::Not => combinator_config.filters.len() == 1,
+                _ => !combinator_config.filters.is_empty(),
+            };
+            if !expected {
+                return Err(ConfigError::InvalidFilter {
+                    name: name.to_owned(),
+                    path: format!("filters.{}.filters", name),
+                    source: serde::de::Error::custom(match kind {
+                        CombinatorKind::Not => "'not' takes exactly one filter",
+                        _ => "expected at least one filter",
+                    }),
+                });
+            }
+
+            parents.push(name.to_owned());
+            let filters = combinator_config
+                .filters
+                .iter()
+                .map(
+                    |child| match self.load_nested_filter(config, child, parents)? {
+                        (filter, FilterMode::Enforce) => Ok(filter),
+                        (_, FilterMode::Monitor) => Err(ConfigError::InvalidFilter {
+                            name: child.to_owned(),
+                            path: format!("filters.{}.mode", child),
+                            source: serde::de::Error::custom(
+                                "only filters in a pipeline can be monitored",
+                            ),
+                        }),
+                    },
+                )
+                .collect::<Result<Vec<_>, _>>()?;
+            parents.pop();
+
+            let mut combinator = Combinator::new(kind, filters);
+            if let Some(action) = combinator_config.action {
+                combinator = combinator.with_action(action);
+            }
+            if let Some(message) = combinator_config.message {
+                combinator = combinator.with_message(message);
+            }
+
+            return Ok((Box::new(combinator), mode));
+        }
+
+        let constructor = self
+            .registered_filters
+            .get(filter_type.as_str())
+            .ok_or_else(|| ConfigError::UnknownFilter {
+                name: name.to_owned(),
+                filter_type: filter_type.clone(),
+            })?;
+
+        let filter =
+            constructor(config_value, &self.throttled).map_err(|err| invalid_filter(name, err))?;
+        Ok((filter, mode))
+    }
+
+    /// Parse a TOML config string and load it, see [`Noteguard::load_config`]
+    pub fn load_config_str(&mut self, config: &str) -> Result<(), ConfigError> {
+        self.load_config(&config.parse()?)
+    }
+
+    /// Read a TOML config file and load it, see [`Noteguard::load_config`]
+    pub fn load_config_path(&mut self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        self.load_config(&Config::from_path(path)?)
+    }
+
+    /// Read a TOML config file and reload it, see [`Noteguard::reload_config`]
+    pub fn reload_config_path(&mut self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        self.reload_config(&Config::from_path(path)?)
+    }
+}
+
+/// The filter type of a `[filters.<name>]` section. This is the `type` key
+/// if there is one, which is removed from the settings, otherwise it is the
+/// section name itself.
+fn filter_type(name: &str, config_value: &mut toml::Value) -> Result<String, ConfigError> {
+    let Some(filter_type) = config_value.as_table_mut().and_then(|t| t.remove("type")) else {
+        return Ok(name.to_owned());
+    };
+
+    match filter_type {
+        toml::Value::String(filter_type) => Ok(filter_type),
+        _ => Err(ConfigError::InvalidFilter {
+            name: name.to_owned(),
+            path: format!("filters.{}.type", name),
+            source: serde::de::Error::custom("filter type must be a string"),
+        }),
+    }
+}
+
+fn invalid_filter(name: &str, err: SettingsError) -> ConfigError {
+    let path = match err.path.as_str() {
+        "." => format!("filters.{}", name),
+        field => format!("filters.{}.{}", name, field),
+    };
+
+    ConfigError::InvalidFilter {
+        name: name.to_owned(),
+        path,
+        source: err.source,
+    }
+}
+
+/// The names of the given filters and every filter they refer to through
+/// combinators, in the order they are found
+fn referenced_filters<'a>(config: &'a Config, names: Vec<&'a str>) -> Vec<&'a str> {
+    let mut referenced: Vec<&str> = vec![];
+    let mut todo: Vec<&str> = names.into_iter().rev().collect();
+
+    while let Some(name) = todo.pop() {
+        if referenced.contains(&name) {
+            continue;
+        }
+        referenced.push(name);
+
+        let Some(table) = config.filters.get(name).and_then(|value| value.as_table()) else {
+            continue;
+        };
+
+        // like the loader, sections without a type are named after theirs
+        let filter_type = match table.get("type") {
+            Some(filter_type) => filter_type.as_str(),
+            None => Some(name),
+        };
+        let is_combinator = filter_type.and_then(CombinatorKind::from_type).is_some();
+
+        if let (true, Some(filters)) = (
+            is_combinator,
+            table.get("filters").and_then(|f| f.as_array()),
+        ) {
+            todo.extend(filters.iter().rev().filter_map(|f| f.as_str()));
+        }
+    }
+
+    referenced
+}
+
+/// The settings of a filter and of every filter it is made of. If these
+/// are unchanged on reload, the filter can keep its state.
+fn resolved_config(config: &Config, name: &str) -> Option<toml::Value> {
+    let resolved = referenced_filters(config, vec![name])
+        .into_iter()
+        .map(|name| {
+            let mut value = config.filters.get(name)?.clone();
+            let filter_type = match value.get("type") {
+                Some(filter_type) => filter_type.as_str()?.to_owned(),
+                None => name.to_owned(),
+            };
+            config
+                .substitute_lists(name, &filter_type, &mut value)
+                .ok()?;
+            Some(toml::Value::Array(vec![name.into(), value]))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(toml::Value::Array(resolved))
+}
+
+/// The mode of a `[filters.<name>]` section. This is the `mode` key, which
+/// is removed from the settings.
+fn filter_mode(name: &str, config_value: &mut toml::Value) -> Result<FilterMode, ConfigError> {
+    let Some(mode) = config_value.as_table_mut().and_then(|t| t.remove("mode")) else {
+        return Ok(FilterMode::Enforce);
+    };
+
+    mode.try_into()
+        .map_err(|source| ConfigError::InvalidFilter {
+            name: name.to_owned(),
+            path: format!("filters.{}.mode", name),
+            source,
+        })
+}
+
+/// Filter sections that aren't used by the pipeline or by any route, sorted
+/// by name
+fn unused_filters(config: &Config) -> Vec<String> {
+    let referenced = referenced_filters(config, config.pipeline_names());
+    let mut unused: Vec<String> = config
+        .filters
+        .keys()
+        .filter(|name| !referenced.contains(&name.as_str()))
+        .cloned()
+        .collect();
+    unused.sort();
+    unused
+}
+
+/// Builds a [`Noteguard`] pipeline in code instead of from a config file.
+///
+/// ```
+/// use noteguard::filters::{Kinds, ProtectedEvents};
+/// use noteguard::Noteguard;
+///
+/// let noteguard = Noteguard::builder()
+///     .filter(ProtectedEvents::default())
+///     .filter(Kinds::default())
+///     .build();
+///
+/// assert_eq!(noteguard.len(), 2);
+/// ```
+pub struct NoteguardBuilder {
+    noteguard: Noteguard,
+}
+
+impl Default for NoteguardBuilder {
+    fn default() -> Self {
+        NoteguardBuilder {
+            noteguard: Noteguard::new(),
+        }
+    }
+}
+
+impl NoteguardBuilder {
+    /// Make a custom filter available to configs loaded by this pipeline
+    pub fn register_filter<F: NoteFilter + 'static + Default + DeserializeOwned>(mut self) -> Self {
+        self.noteguard.register_filter::<F>();
+        self
+    }
+
+    /// Append a filter to the end of the pipeline
+    pub fn filter<F: NoteFilter + 'static>(mut self, filter: F) -> Self {
+        self.noteguard.push_filter(Box::new(filter));
+        self
+    }
+
+    /// Load the pipeline from a config. This replaces any filters added
+    /// with [`NoteguardBuilder::filter`] so far.
+    pub fn config(mut self, config: &Config) -> Result<Self, ConfigError> {
+        self.noteguard.load_config(config)?;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Noteguard {
+        self.noteguard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::{ProtectedEvents, Whitelist};
+    use crate::test_utils::{create_mock_input_message, MOCK_PUBKEY, OTHER_PUBKEY};
+    use crate::{Action, Kind, List};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_register_builtin_filters() {
+        let noteguard = Noteguard::new();
+        assert!(noteguard.registered_filters.contains_key("ratelimit"));
+        assert!(noteguard.registered_filters.contains_key("whitelist"));
+        assert!(noteguard.registered_filters.contains_key("blacklist"));
+        assert!(noteguard
+            .registered_filters
+            .contains_key("protected_events"));
+        assert!(noteguard.registered_filters.contains_key("kinds"));
+    }
+
+    #[test]
+    fn test_load_config() {
+        let mut noteguard = Noteguard::new();
+
+        // Create a mock config with one filter (RateLimit)
+        let config: Config = toml::from_str(
+            r#"
+            pipeline = ["ratelimit"]
+
+            [filters.ratelimit]
+            posts_per_minute = 3
+        "#,
+        )
+        .expect("Failed to parse config");
+
+        assert!(noteguard.load_config(&config).is_ok());
+        assert_eq!(noteguard.loaded_filters.len(), 1);
+        assert_eq!(noteguard.len(), 1);
+    }
+
+    #[test]
+    fn test_run_filters_accept() {
+        let mut noteguard = Noteguard::new();
+
+        // Create a mock config with one filter (RateLimit)
+        let config: Config = toml::from_str(
+            r#"
+            pipeline = ["ratelimit"]
+
+            [filters.ratelimit]
+            posts_per_minute = 3
+        "#,
+        )
+        .expect("Failed to parse config");
+
+        noteguard
+            .load_config(&config)
+            .expect("Failed to load config");
+
+        let input_message = create_mock_input_message("test_event_1", "new");
+        let output_message = noteguard.run(input_message);
+
+        assert_eq!(output_message.action, Action::Accept);
+    }
+
+    #[test]
+    fn test_run_filters_shadow_reject() {
+        let mut noteguard = Noteguard::new();
+
+        // Create a mock config with one filter (ProtectedEvents) which will shadow reject the input
+        let config: Config = toml::from_str(
+            r#"
+            pipeline = ["protected_events"]
+
+            [filters.protected_events]
+        "#,
+        )
+        .expect("Failed to parse config");
+
+        noteguard
+            .load_config(&config)
+            .expect("Failed to load config");
+
+        let input_message = create_mock_input_message("test_event_3", "new");
+        let output_message = noteguard.run(input_message);
+
+        assert_eq!(output_message.action, Action::Reject);
+    }
+
+    #[test]
+    fn test_whitelist_reject() {
+        let mut noteguard = Noteguard::new();
+
+        // Create a mock config with one filter (Whitelist) which will reject the input
+        let config: Config = toml::from_str(
+            r#"
+            pipeline = ["whitelist"]
+            [filters.whitelist]
+            pubkeys = ["5f54041530509de28550475bfe73db709609a4ee1e59281527ba81692923418f"]
+        "#,
+        )
+        .expect("Failed to parse config");
+
+        noteguard
+            .load_config(&config)
+            .expect("Failed to load config");
+
+        let input_message = create_mock_input_message("test_event_2", "new");
+        let output_message = noteguard.run(input_message);
+
+        assert_eq!(output_message.action, Action::Reject);
+    }
+
+    #[test]
+    fn test_blacklist_reject() {
+        let mut noteguard = Noteguard::new();
+
+        let config: Config = toml::from_str(
+            r#"
+            pipeline = ["blacklist"]
+            [filters.blacklist]
+            pubkeys = ["16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93"]
+        "#,
+        )
+        .expect("Failed to parse config");
+
+        noteguard
+            .load_config(&config)
+            .expect("Failed to load config");
+
+        let input_message = create_mock_input_message("test_event_3", "new");
+        let output_message = noteguard.run(input_message);
+
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.expect("Failed to get message"),
+            "blocked: pubkey/ip is blacklisted".to_string()
+        );
+    }
+
+    #[test]
+    fn test_blacklist_accept() {
+        let mut noteguard = Noteguard::new();
+
+        let config: Config = toml::from_str(
+            r#"
+            pipeline = ["blacklist"]
+            [filters.blacklist]
+            pubkeys = ["5f54041530509de28550475bfe73db709609a4ee1e59281527ba81692923418f"]
+        "#,
+        )
+        .expect("Failed to parse config");
+
+        noteguard
+            .load_config(&config)
+            .expect("Failed to load config");
+
+        let input_message = create_mock_input_message("test_event_4", "new");
+        let output_message = noteguard.run(input_message);
+
+        assert_eq!(output_message.action, Action::Accept);
+    }
+
+    #[test]
+    fn test_load_config_str() {
+        let mut noteguard = Noteguard::new();
+
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["protected_events", "kinds"]
+
+                [filters.protected_events]
+
+                [filters.kinds]
+                kinds = [1]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        assert_eq!(noteguard.len(), 2);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut noteguard = Noteguard::builder()
+            .filter(Whitelist {
+                pubkeys: Some(List::inline(HashSet::from([MOCK_PUBKEY.parse().unwrap()]))),
+                ips: None,
+                allowlist: false,
+            })
+            .filter(ProtectedEvents::default())
+            .build();
+
+        let input_message = create_mock_input_message("test_event_6", "new");
+        let output_message = noteguard.run(input_message);
+
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: event marked as protected")
+        );
+    }
+
+    #[test]
+    fn test_builder_register_filter() {
+        #[derive(serde::Deserialize, Default)]
+        struct Nope {}
+
+        impl NoteFilter for Nope {
+            fn filter_note(&mut self, msg: &InputMessage) -> OutputMessage {
+                OutputMessage::new(msg.event.id.to_string(), Action::ShadowReject, None)
+            }
+
+            fn name(&self) -> &'static str {
+                "nope"
+            }
+        }
+
+        let mut noteguard = Noteguard::builder().register_filter::<Nope>().build();
+
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["kinds", "nope"]
+
+                [filters.kinds]
+                kinds = [7]
+
+                [filters.nope]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let input_message = create_mock_input_message("test_event_7", "new");
+        let output_message = noteguard.run(input_message);
+
+        assert_eq!(output_message.action, Action::ShadowReject);
+    }
+
+    #[test]
+    fn test_load_config_missing_filter() {
+        let mut noteguard = Noteguard::new();
+
+        let err = noteguard
+            .load_config_str(r#"pipeline = ["kinds"]"#)
+            .expect_err("config should not load");
+
+        assert!(matches!(err, ConfigError::MissingFilter { name } if name == "kinds"));
+    }
+
+    #[test]
+    fn test_load_config_unknown_filter() {
+        let mut noteguard = Noteguard::new();
+
+        let err = noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["kinsd"]
+                [filters.kinsd]
+            "#,
+            )
+            .expect_err("config should not load");
+
+        assert!(
+            matches!(err, ConfigError::UnknownFilter { name, filter_type } if name == "kinsd" && filter_type == "kinsd")
+        );
+    }
+
+    #[test]
+    fn test_load_config_invalid_filter() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["protected_events"]
+                [filters.protected_events]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let err = noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["ratelimit"]
+                [filters.ratelimit]
+                posts_per_minute = "lots"
+            "#,
+            )
+            .expect_err("config should not load");
+
+        match err {
+            ConfigError::InvalidFilter { name, path, .. } => {
+                assert_eq!(name, "ratelimit");
+                assert_eq!(path, "filters.ratelimit.posts_per_minute");
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+
+        // the previous pipeline is kept
+        assert_eq!(noteguard.len(), 1);
+    }
+
+    #[test]
+    fn test_load_config_unknown_setting() {
+        let mut noteguard = Noteguard::new();
+
+        for (config, name, expected) in [
+            (
+                r#"
+                pipeline = ["whitelist"]
+                [filters.whitelist]
+                pubkey = ["16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93"]
+            "#,
+                "whitelist",
+                "filters.whitelist.pubkey",
+            ),
+            (
+                r#"
+                pipeline = ["timestamps"]
+                [filters.timestamps]
+                max_futur = 60
+            "#,
+                "timestamps",
+                "filters.timestamps",
+            ),
+            (
+                r#"
+                pipeline = ["size"]
+                [filters.size]
+                type = "limits"
+                [filters.size.kinds.1]
+                max_size = 10
+            "#,
+                "size",
+                "filters.size.kinds.1.max_size",
+            ),
+        ] {
+            match noteguard.load_config_str(config) {
+                Err(ConfigError::InvalidFilter { name: n, path, .. }) => {
+                    assert_eq!(n, name);
+                    assert_eq!(path, expected);
+                }
+                Err(err) => panic!("unexpected error: {}", err),
+                Ok(()) => panic!("config with {} should not load", expected),
+            }
+        }
+
+        let err = noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["protected_events"]
+                [filters.protected_events]
+                [filter.kinds]
+                kinds = [1]
+            "#,
+            )
+            .expect_err("config should not load");
+
+        match err {
+            ConfigError::UnknownSetting { path } => assert_eq!(path, "filter"),
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_check_config() {
+        let noteguard = Noteguard::new();
+
+        let config: Config = r#"
+            pipeline = ["kinds", "nope"]
+
+            [filters.kinds]
+            kinds = "1"
+
+            [filters.content]
+            filters = []
+        "#
+        .parse()
+        .expect("Failed to parse config");
+
+        let errors: Vec<String> = noteguard
+            .check_config(&config)
+            .iter()
+            .map(|err| err.to_string())
+            .collect();
+
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("invalid settings for filter 'kinds' at filters.kinds.kinds"));
+        assert_eq!(
+            errors[1],
+            "pipeline entry 'nope' has no [filters.nope] section"
+        );
+        assert_eq!(errors[2], "[filters.content] is not used by the pipeline");
+    }
+
+    #[test]
+    fn test_reload_config_keeps_state() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["ratelimit"]
+                [filters.ratelimit]
+                posts_per_minute = 1
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_8", "new"));
+        assert_eq!(output_message.action, Action::Accept);
+
+        // the ratelimit settings did not change, so its buckets are kept
+        let config: Config = r#"
+            pipeline = ["kinds", "ratelimit"]
+            [filters.kinds]
+            kinds = [7]
+            [filters.ratelimit]
+            posts_per_minute = 1
+        "#
+        .parse()
+        .expect("Failed to parse config");
+        noteguard
+            .reload_config(&config)
+            .expect("Failed to reload config");
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_9", "new"));
+        assert_eq!(output_message.action, Action::Reject);
+
+        // changing the settings starts with fresh state
+        let config: Config = r#"
+            pipeline = ["ratelimit"]
+            [filters.ratelimit]
+            posts_per_minute = 2
+        "#
+        .parse()
+        .expect("Failed to parse config");
+        noteguard
+            .reload_config(&config)
+            .expect("Failed to reload config");
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_10", "new"));
+        assert_eq!(output_message.action, Action::Accept);
+    }
+
+    #[test]
+    fn test_named_filter_instances() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["no_reactions", "no_notes"]
+
+                [filters.no_reactions]
+                type = "kinds"
+                kinds = [7]
+
+                [filters.no_notes]
+                type = "kinds"
+                kinds = [1]
+                messages = { 1 = "blocked: no notes here" }
+            "#,
+            )
+            .expect("Failed to load config");
+
+        assert_eq!(noteguard.len(), 2);
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_11", "new"));
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: no notes here")
+        );
+
+        let err = noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["strict"]
+                [filters.strict]
+                type = "ratelimt"
+            "#,
+            )
+            .expect_err("config should not load");
+
+        assert_eq!(
+            err.to_string(),
+            "pipeline entry 'strict' has unknown filter type 'ratelimt'"
+        );
+    }
+
+    #[test]
+    fn test_whitelist_allowlist() {
+        let mut noteguard = Noteguard::new();
+        let config = r#"
+            pipeline = ["whitelist", "protected_events"]
+
+            [filters.whitelist]
+            pubkeys = ["16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93"]
+            allowlist = true
+
+            [filters.protected_events]
+        "#;
+        noteguard
+            .load_config_str(config)
+            .expect("Failed to load config");
+
+        // a match skips the rest of the pipeline
+        let output_message = noteguard.run(create_mock_input_message("test_event_12", "new"));
+        assert_eq!(output_message.action, Action::AcceptFinal);
+
+        // no match moves on to the next filter
+        noteguard
+            .load_config_str(&config.replace(MOCK_PUBKEY, OTHER_PUBKEY))
+            .expect("Failed to load config");
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_13", "new"));
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: event marked as protected")
+        );
+    }
+
+    #[test]
+    fn test_combinators() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["protected_notes"]
+
+                [filters.protected_notes]
+                type = "all"
+                filters = ["not_reactions", "protected_events"]
+                action = "shadowReject"
+
+                [filters.not_reactions]
+                type = "not"
+                filters = ["reactions"]
+
+                [filters.reactions]
+                type = "kinds"
+                kinds = [7]
+
+                [filters.protected_events]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_14", "new"));
+        assert_eq!(output_message.action, Action::ShadowReject);
+
+        let mut input_message = create_mock_input_message("test_event_15", "new");
+        input_message.event.kind = Kind(7);
+        let output_message = noteguard.run(input_message);
+        assert_eq!(output_message.action, Action::Accept);
+    }
+
+    #[test]
+    fn test_combinator_any_first() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["group"]
+
+                [filters.group]
+                type = "first"
+                filters = ["staff", "spam"]
+
+                [filters.staff]
+                type = "whitelist"
+                pubkeys = ["16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93"]
+                allowlist = true
+
+                [filters.spam]
+                type = "any"
+                filters = ["kinds", "protected_events"]
+                message = "blocked: spam"
+
+                [filters.kinds]
+                kinds = [1]
+
+                [filters.protected_events]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_16", "new"));
+        assert_eq!(output_message.action, Action::AcceptFinal);
+
+        let mut input_message = create_mock_input_message("test_event_17", "new");
+        input_message.event.pubkey = OTHER_PUBKEY.parse().unwrap();
+        let output_message = noteguard.run(input_message);
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(output_message.msg.as_deref(), Some("blocked: spam"));
+    }
+
+    #[test]
+    fn test_reload_untyped_combinator() {
+        let config = r#"
+            pipeline = ["any"]
+
+            [filters.any]
+            filters = ["kinds", "protected_events"]
+
+            [filters.kinds]
+            kinds = [7]
+
+            [filters.protected_events]
+        "#;
+
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(config)
+            .expect("Failed to load config");
+        assert!(noteguard
+            .check_config(&config.parse().expect("Failed to parse config"))
+            .is_empty());
+
+        let run = |noteguard: &mut Noteguard, label: &str, kind: u16| {
+            let mut input_message = create_mock_input_message(label, "new");
+            input_message.event.kind = Kind(kind);
+            input_message.event.tags = Default::default();
+            noteguard.run(input_message).action
+        };
+        assert_eq!(run(&mut noteguard, "test_event_86", 7), Action::Reject);
+
+        // a changed child replaces the combinator
+        noteguard
+            .reload_config(
+                &config
+                    .replace("kinds = [7]", "kinds = [9]")
+                    .parse()
+                    .expect("Failed to parse config"),
+            )
+            .expect("Failed to reload config");
+        assert_eq!(run(&mut noteguard, "test_event_87", 7), Action::Accept);
+        assert_eq!(run(&mut noteguard, "test_event_88", 9), Action::Reject);
+    }
+
+    #[test]
+    fn test_combinator_errors() {
+        let noteguard = Noteguard::new();
+
+        let config: Config = r#"
+            pipeline = ["loop", "bad_not"]
+
+            [filters.loop]
+            type = "any"
+            filters = ["inner"]
+
+            [filters.inner]
+            type = "all"
+            filters = ["loop"]
+
+            [filters.bad_not]
+            type = "not"
+            filters = []
+        "#
+        .parse()
+        .expect("Failed to parse config");
+
+        let errors: Vec<String> = noteguard
+            .check_config(&config)
+            .iter()
+            .map(|err| err.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "filter 'loop' includes itself".to_string(),
+                "invalid settings for filter 'bad_not' at filters.bad_not.filters: 'not' takes exactly one filter".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_routes() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["ratelimit", "protected_events"]
+
+                [[routes]]
+                kinds = [4, 1059]
+                pipeline = ["ratelimit"]
+
+                [[routes]]
+                sourceType = ["Import", "Sync"]
+                pipeline = []
+
+                [[routes]]
+                sources = ["10.0.0.0/8"]
+                pipeline = []
+
+                [filters.ratelimit]
+                posts_per_minute = 1
+
+                [filters.protected_events]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        assert_eq!(noteguard.loaded_filters.len(), 2);
+
+        let mut input_message = create_mock_input_message("test_event_18", "new");
+        input_message.source_type = "Import".to_string();
+        let output_message = noteguard.run(input_message);
+        assert_eq!(output_message.action, Action::Accept);
+
+        let mut input_message = create_mock_input_message("test_event_19", "new");
+        input_message.event.kind = Kind(4);
+        let output_message = noteguard.run(input_message);
+        assert_eq!(output_message.action, Action::Accept);
+
+        // the ratelimit is shared with the default pipeline, which has
+        // already seen this source once
+        let output_message = noteguard.run(create_mock_input_message("test_event_20", "new"));
+        assert_eq!(output_message.action, Action::Reject);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("rate-limited: you are noting too much")
+        );
+
+        // sources match client ips by range, and nothing else
+        let mut input_message = create_mock_input_message("test_event_84", "new");
+        input_message.source_type = "IP4".to_string();
+        input_message.source_info = "10.1.2.3".to_string();
+        assert_eq!(noteguard.run(input_message).action, Action::Accept);
+
+        let mut input_message = create_mock_input_message("test_event_85", "new");
+        input_message.source_type = "Stream".to_string();
+        input_message.source_info = "10.1.2.3".to_string();
+        let output_message = noteguard.run(input_message);
+        assert_eq!(
+            output_message.msg.as_deref(),
+            Some("blocked: event marked as protected")
+        );
+    }
+
+    #[test]
+    fn test_monitor_mode() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["protected_events", "kinds"]
+
+                [filters.protected_events]
+                mode = "monitor"
+
+                [filters.kinds]
+                kinds = [7]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let output_message = noteguard.run(create_mock_input_message("test_event_21", "new"));
+        assert_eq!(output_message.action, Action::Accept);
+
+        let mut input_message = create_mock_input_message("test_event_22", "new");
+        input_message.event.kind = Kind(7);
+        let output_message = noteguard.run(input_message);
+        assert_eq!(output_message.action, Action::Reject);
+
+        let err = noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["any"]
+
+                [filters.any]
+                filters = ["kinds"]
+
+                [filters.kinds]
+                kinds = [7]
+                mode = "monitor"
+            "#,
+            )
+            .expect_err("config should not load");
+
+        assert_eq!(
+            err.to_string(),
+            "invalid settings for filter 'kinds' at filters.kinds.mode: only filters in a pipeline can be monitored"
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["kinds", "protected_events"]
+
+                [filters.kinds]
+                kinds = [7]
+                mode = "monitor"
+
+                [filters.protected_events]
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let mut input_message = create_mock_input_message("test_event_23", "new");
+        input_message.event.kind = Kind(7);
+        let decision = noteguard.evaluate(&input_message);
+
+        assert_eq!(decision.output.action, Action::Reject);
+        assert_eq!(decision.filter.as_deref(), Some("protected_events"));
+        assert_eq!(decision.timings.len(), 2);
+        assert_eq!(decision.timings[0].name, "kinds");
+        assert_eq!(decision.timings[0].action, Action::Reject);
+        assert!(decision.timings[0].monitor);
+    }
+
+    #[test]
+    fn test_handle_lookback() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["ratelimit"]
+                [filters.ratelimit]
+                posts_per_minute = 1
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let line = r#"{"type":"lookback","receivedAt":12345,"sourceType":"IP4","sourceInfo":"127.0.0.2","event":{"id": "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76","pubkey": "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93","created_at": 1720408658,"kind": 1,"tags": [],"content": "hi","sig": "7b76471744ded2b720ca832cdc89e670f6093ce38aeef55a5c6a4e077883d7d80dda1e9051032fb1faa1c3c212c517e93ee42b3ceac8e8e9b04bad46a361de90"}}"#;
+        assert!(noteguard.handle_line(line).is_none());
+
+        // the lookback message used up the ratelimit
+        let out = noteguard
+            .handle_line(&line.replace("lookback", "new"))
+            .expect("expected a response");
+        assert_eq!(out.action, Action::Reject);
+
+        let out = noteguard
+            .handle_line(&line.replace("lookback", "sync"))
+            .expect("expected a response");
+        assert_eq!(out.action, Action::Reject);
+        assert_eq!(
+            out.msg.as_deref(),
+            Some("invalid strfry write policy input")
+        );
+
+        // unparsable lookback lines don't get a response either
+        let malformed = line.replace(r#""kind": 1"#, r#""kind": "one""#);
+        assert!(noteguard.handle_line(&malformed).is_none());
+
+        let out = noteguard
+            .handle_line(&malformed.replace("lookback", "new"))
+            .expect("expected a response");
+        assert_eq!(
+            out.id,
+            "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76"
+        );
+        assert_eq!(out.action, Action::Reject);
+    }
+
+    #[test]
+    fn test_audit_rejected_input() {
+        let path = std::env::temp_dir().join(format!("noteguard-input-{}", std::process::id()));
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(&format!(
+                r#"
+                pipeline = ["protected_events"]
+                [filters.protected_events]
+                [audit]
+                path = "{}"
+            "#,
+                path.display()
+            ))
+            .expect("Failed to load config");
+
+        let line = r#"{"type":"new","receivedAt":12345,"sourceType":"IP4","sourceInfo":"127.0.0.2","event":{"id": "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76","pubkey": "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93","created_at": 1720408658,"kind": 1,"tags": [],"content": "hi","sig": "7b76471744ded2b720ca832cdc89e670f6093ce38aeef55a5c6a4e077883d7d80dda1e9051032fb1faa1c3c212c517e93ee42b3ceac8e8e9b04bad46a361de90"}}"#;
+        noteguard.handle_line(&line.replace(r#""kind": 1"#, r#""kind": "one""#));
+        noteguard.handle_line(&line.replace(r#""type":"new""#, r#""type":"sync""#));
+
+        let audit = std::fs::read_to_string(&path).expect("Failed to read audit log");
+        let records: Vec<serde_json::Value> = audit
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("invalid json"))
+            .collect();
+        assert_eq!(records.len(), 2);
+
+        for record in &records {
+            assert_eq!(
+                record["id"],
+                "68421a122cef086512b2c5bd29ca6285ced8bd8e302e347e3c5d90466c860a76"
+            );
+            assert_eq!(record["action"], "reject");
+            assert_eq!(record["filter"], "input");
+        }
+
+        // only the parsed line has the rest of the note
+        assert!(records[0].get("kind").is_none());
+        assert_eq!(records[1]["kind"], 1);
+        assert_eq!(records[1]["msg"], "invalid strfry write policy input");
+
+        std::fs::remove_file(&path).expect("Failed to remove audit log");
+    }
+
+    #[test]
+    fn test_audit_open_error() {
+        let err = Noteguard::new()
+            .load_config_str(
+                r#"
+                pipeline = []
+
+                [audit]
+                path = "/nonexistent/noteguard/audit.jsonl"
+            "#,
+            )
+            .expect_err("unwritable audit log should fail");
+        assert!(matches!(err, ConfigError::Audit { .. }));
+        assert!(err
+            .to_string()
+            .starts_with("could not open audit log /nonexistent/noteguard/audit.jsonl:"));
+    }
+
+    #[test]
+    fn test_accepted_notes() {
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(
+                r#"
+                pipeline = ["urls", "limits"]
+
+                [filters.urls]
+                max_links_unknown = 0
+                known_after = 1
+
+                [filters.limits]
+                max_content_bytes = 20
+            "#,
+            )
+            .expect("Failed to load config");
+
+        let run = |noteguard: &mut Noteguard, label: &str, content: &str| {
+            let mut input_message = create_mock_input_message(label, "new");
+            input_message.event.content = content.to_string();
+            noteguard.run(input_message).action
+        };
+
+        // rejected by a later filter, so the pubkey is still unknown
+        assert_eq!(
+            run(&mut noteguard, "long_note", "this note is too long to post"),
+            Action::Reject
+        );
+        assert_eq!(
+            run(&mut noteguard, "link_note", "https://damus.io"),
+            Action::Reject
+        );
+
+        assert_eq!(run(&mut noteguard, "short_note", "gm"), Action::Accept);
+        assert_eq!(
+            run(&mut noteguard, "known_link_note", "https://damus.io"),
+            Action::Accept
+        );
+    }
+
+    #[test]
+    fn test_named_lists() {
+        let path = std::env::temp_dir().join(format!("noteguard-domains-{}", std::process::id()));
+        std::fs::write(&path, "# spam\nspam.example\n").expect("Failed to write list");
+
+        let config = format!(
+            r#"
+            pipeline = ["whitelist", "urls"]
+
+            [lists.staff]
+            type = "pubkeys"
+            entries = ["{}"]
+
+            [lists.spam_domains]
+            type = "domains"
+            file = "{}"
+
+            [filters.whitelist]
+            pubkeys = "@staff"
+            allowlist = true
+
+            [filters.urls]
+            block_suffixes = "@spam_domains"
+            message = "@spam_domains"
+        "#,
+            MOCK_PUBKEY,
+            path.display()
+        );
+
+        let mut noteguard = Noteguard::new();
+        noteguard
+            .load_config_str(&config)
+            .expect("Failed to load config");
+
+        let mut input_message = create_mock_input_message("test_event_82", "new");
+        input_message.event.content = "https://cdn.spam.example/x.png".to_string();
+        assert_eq!(noteguard.run(input_message).action, Action::AcceptFinal);
+
+        let mut input_message = create_mock_input_message("test_event_83", "new");
+        input_message.event.pubkey = OTHER_PUBKEY.parse().expect("invalid pubkey");
+        input_message.event.content = "https://cdn.spam.example/x.png".to_string();
+        let output = noteguard.run(input_message);
+        assert_eq!(output.action, Action::Reject);
+        // only list settings use lists, messages are kept as they are
+        assert_eq!(output.msg.as_deref(), Some("@spam_domains"));
+
+        let err = Noteguard::new()
+            .load_config_str(&config.replace(MOCK_PUBKEY, "not-a-pubkey"))
+            .expect_err("invalid list entry should fail");
+        assert_eq!(
+            err.to_string(),
+            "invalid list [lists.staff]: entries[0]: 'not-a-pubkey' is not a hex pubkey"
+        );
+
+        let unknown = config.replace("\"@staff\"", "\"@admins\"");
+        let errors = Noteguard::new().check_config(&unknown.parse().expect("Failed to parse"));
+        assert!(matches!(
+            errors.as_slice(),
+            [ConfigError::UnknownList { filter, list }] if filter == "whitelist" && list == "admins"
+        ));
+
+        let err = Noteguard::new()
+            .load_config_str(&unknown)
+            .expect_err("unknown list should fail");
+        assert_eq!(
+            err.to_string(),
+            "filter 'whitelist' uses '@admins', but there is no [lists.admins] section"
+        );
+
+        let err = Noteguard::new()
+            .load_config_str(&config.replace("pubkeys = \"@staff\"", "ips = \"@staff\""))
+            .expect_err("wrong list type should fail");
+        assert_eq!(
+            err.to_string(),
+            "filter 'whitelist' setting 'ips' needs a list of type 'ips', but [lists.staff] has type 'pubkeys'"
+        );
+
+        std::fs::remove_file(&path).expect("Failed to remove list");
+    }
+}
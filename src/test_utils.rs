@@ -0,0 +1,46 @@
+//! Helpers shared by the unit tests of the pipeline and of each filter
+
+use crate::{InputMessage, Kind, Note, NoteId, Signature, Tag, Tags};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+
+pub const MOCK_PUBKEY: &str = "16c21558762108afc34e4ff19e4ed51d9a48f79e0c34531efc423d21ab435e93";
+pub const OTHER_PUBKEY: &str = "5f54041530509de28550475bfe73db709609a4ee1e59281527ba81692923418f";
+
+// A unique note id for each test event
+pub fn mock_id(event_id: &str) -> NoteId {
+    NoteId::from_bytes(Sha256::digest(event_id).into())
+}
+
+// Helper function to create a mock InputMessage
+pub fn create_mock_input_message(event_id: &str, message_type: &str) -> InputMessage {
+    InputMessage {
+        message_type: message_type.to_string(),
+        event: Note {
+            id: mock_id(event_id),
+            pubkey: MOCK_PUBKEY.parse().unwrap(),
+            created_at: 0,
+            kind: Kind(1),
+            tags: Tags(vec![Tag(vec!["-".to_string()])]),
+            content: "mock_content".to_string(),
+            sig: Signature::from_bytes([0; 64]),
+        },
+        received_at: 0,
+        source_type: "mock_source".to_string(),
+        source_info: "mock_source_info".to_string(),
+    }
+}
+
+// A "new" message, for filters that don't care about the note id
+pub fn mock_input() -> InputMessage {
+    create_mock_input_message("mock_event", "new")
+}
+
+// Build a filter from the body of its `[filters.<name>]` table
+pub fn load_filter<F: DeserializeOwned>(config: &str) -> F {
+    toml::from_str(config).expect("Failed to load filter config")
+}
+
+pub fn tags(tags: serde_json::Value) -> Tags {
+    serde_json::from_value(tags).expect("Failed to parse tags")
+}
@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls a file's modification time to detect changes. Checks are
+/// throttled, so it is cheap to call [`FileWatcher::changed`] on every note.
+pub struct FileWatcher {
+    path: PathBuf,
+    interval: Duration,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl FileWatcher {
+    /// Watch a file, checking for changes at most once per `interval`. The
+    /// current modification time is taken as unchanged.
+    pub fn new(path: impl AsRef<Path>, interval: Duration) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified_time(&path);
+
+        FileWatcher {
+            path,
+            interval,
+            modified,
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if the file was modified since the last time this
+    /// returned true. A missing file is never considered changed.
+    pub fn changed(&mut self) -> bool {
+        let now = Instant::now();
+        if now.duration_since(self.last_check) < self.interval {
+            return false;
+        }
+        self.last_check = now;
+
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_file_watcher() {
+        let path = std::env::temp_dir().join(format!("noteguard-watch-{}", std::process::id()));
+        let file = File::create(&path).expect("Failed to create file");
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1000))
+            .expect("Failed to set mtime");
+
+        let mut watcher = FileWatcher::new(&path, Duration::ZERO);
+        assert!(!watcher.changed());
+
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(2000))
+            .expect("Failed to set mtime");
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::remove_file(&path).expect("Failed to remove file");
+        assert!(!watcher.changed());
+    }
+}